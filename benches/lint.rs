@@ -0,0 +1,65 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! End-to-end benchmarks of linting a generated fixture tree.
+//!
+//! Compare two revisions with `cargo bench -- --save-baseline before` on the
+//! old one and `cargo bench -- --baseline before` on the new one.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const FILES: usize = 200;
+const LINES: usize = 4000;
+
+/// Writes `FILES` source-like files, one in `dirty_every` of them with issues.
+fn fixture(dir: &Path, dirty_every: usize) -> u64 {
+    let mut bytes = 0;
+    for i in 0..FILES {
+        let sub = dir.join(format!("mod{}", i % 10));
+        fs::create_dir_all(&sub).unwrap();
+        let mut content = String::new();
+        for j in 0..LINES {
+            content.push_str(&format!(
+                "\tlet value_{} = compute(\"naïve – {}\", {});\n",
+                j, i, j
+            ));
+        }
+        if dirty_every > 0 && i % dirty_every == 0 {
+            content.push_str("trailing \r\n\n\n");
+        }
+        bytes += content.len() as u64;
+        fs::write(sub.join(format!("file{}.rs", i)), content).unwrap();
+    }
+    bytes
+}
+
+/// Lints `dir` on a single thread without the cache, to measure scanning.
+fn run(dir: &Path) {
+    let output = Command::new(env!("CARGO_BIN_EXE_clean"))
+        .arg("--git=false")
+        .arg("--jobs=1")
+        .arg("--no-cache")
+        .arg("--json")
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert!(output.status.code().is_some());
+}
+
+fn bench_lint(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lint");
+    for (name, dirty_every) in [("clean_tree", 0), ("dirty_tree", 10)] {
+        let temp = tempfile::tempdir().unwrap();
+        let bytes = fixture(temp.path(), dirty_every);
+        group.throughput(Throughput::Bytes(bytes));
+        group.bench_function(name, |b| b.iter(|| run(temp.path())));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lint);
+criterion_main!(benches);
@@ -0,0 +1,383 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! The built-in rules, one for each whitespace class of [`WsRule`].
+
+use std::sync::Arc;
+
+use crate::rule::{Example, FileEnd, FileInfo, FixedLine, Line, Reporter, Rule};
+use crate::whitespace::{self, WsRule};
+use crate::IssueType;
+
+/// Returns the built-in rules, in the order their issues are reported on a
+/// line.
+pub(crate) fn rules() -> Vec<Arc<dyn Rule>> {
+    vec![
+        Arc::new(TrailingWhitespace),
+        Arc::new(SpaceBeforeTab),
+        Arc::new(IndentWithNonTab),
+        Arc::new(TabInIndent),
+        Arc::new(MissingNewline),
+        Arc::new(CrlfLineEnding),
+        Arc::new(MultipleBlankLinesEof),
+        Arc::new(UnusedSuppression),
+    ]
+}
+
+/// Whether `b` is whitespace, the same as [`char::is_whitespace`] for ASCII.
+fn is_ascii_space(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\x0b' | b'\x0c' | b'\r')
+}
+
+/// Whether `line` ends with whitespace, decoding only its last character.
+fn ends_with_whitespace(line: &[u8]) -> bool {
+    match line.last() {
+        None => false,
+        Some(&b) if b.is_ascii() => is_ascii_space(b),
+        Some(_) => {
+            // A character is at most 4 bytes long.
+            let tail = &line[line.len().saturating_sub(4)..];
+            let start = tail.iter().rposition(|b| b & 0xc0 != 0x80).unwrap_or(0);
+            std::str::from_utf8(&tail[start..])
+                .ok()
+                .and_then(|s| s.chars().next_back())
+                .is_some_and(char::is_whitespace)
+        }
+    }
+}
+
+/// `blank-at-eol`.
+struct TrailingWhitespace;
+
+impl Rule for TrailingWhitespace {
+    fn id(&self) -> &str {
+        IssueType::TrailingWhitespace.id()
+    }
+
+    fn description(&self) -> &str {
+        IssueType::TrailingWhitespace.message()
+    }
+
+    fn explanation(&self) -> &str {
+        "Spaces and tabs at the end of a line are invisible in most editors, \
+         but show up in diffs and make equal looking lines differ. This is the \
+         `blank-at-eol` class of git's `core.whitespace`. Unless `cr-at-eol` \
+         is set, a CR at the end of a line is trailing whitespace too. The fix \
+         removes the whitespace."
+    }
+
+    fn example(&self) -> Option<Example<'_>> {
+        Some(Example {
+            bad: "let x = 1; \nlet y = 2;\t\n",
+            good: "let x = 1;\nlet y = 2;\n",
+            whitespace: "",
+        })
+    }
+
+    fn enabled(&self, ws: &WsRule) -> bool {
+        ws.blank_at_eol
+    }
+
+    fn line_ends_only(&self) -> bool {
+        true
+    }
+
+    fn check_line(&self, line: &Line<'_>, ws: &WsRule) -> bool {
+        // Unless CRLF line endings are accepted, the CR is whitespace too.
+        (ws.crlf && line.cr) || ends_with_whitespace(line.text)
+    }
+
+    fn fixable(&self) -> bool {
+        true
+    }
+
+    fn fix_line(&self, line: &mut FixedLine, ws: &WsRule) {
+        line.text.truncate(line.text.trim_end().len());
+        if ws.crlf {
+            line.cr = false;
+        }
+    }
+}
+
+/// Generates a rule for one of the indent errors of
+/// [`whitespace::IndentErrors`].
+macro_rules! indent_rule {
+    ($name:ident, $class:literal, $field:ident, $explanation:literal, $bad:literal, $good:literal) => {
+        #[doc = concat!("`", $class, "`.")]
+        struct $name;
+
+        impl Rule for $name {
+            fn id(&self) -> &str {
+                IssueType::$name.id()
+            }
+
+            fn description(&self) -> &str {
+                IssueType::$name.message()
+            }
+
+            fn explanation(&self) -> &str {
+                $explanation
+            }
+
+            fn example(&self) -> Option<Example<'_>> {
+                Some(Example {
+                    bad: $bad,
+                    good: $good,
+                    whitespace: $class,
+                })
+            }
+
+            fn enabled(&self, ws: &WsRule) -> bool {
+                ws.$field
+            }
+
+            fn line_ends_only(&self) -> bool {
+                true
+            }
+
+            fn check_line(&self, line: &Line<'_>, ws: &WsRule) -> bool {
+                matches!(line.text.first(), Some(b' ' | b'\t'))
+                    && whitespace::check_indent_bytes(line.text, ws).$field
+            }
+
+            fn fixable(&self) -> bool {
+                true
+            }
+
+            fn fix_line(&self, line: &mut FixedLine, ws: &WsRule) {
+                if whitespace::check_indent(&line.text, ws).$field {
+                    line.text = whitespace::fix_indent(&line.text, ws);
+                }
+            }
+        }
+    };
+}
+
+indent_rule!(
+    SpaceBeforeTab,
+    "space-before-tab",
+    space_before_tab,
+    "A space right before a tab in the indent does not change the width of \
+     the indent with most tab widths, it is usually left over from editing. \
+     This is the `space-before-tab` class of git's `core.whitespace`, \
+     checked by default. The fix rewrites the indent with tabs up to the \
+     last tab.",
+    "if (x) {\n  \treturn;\n}\n",
+    "if (x) {\n\treturn;\n}\n"
+);
+indent_rule!(
+    IndentWithNonTab,
+    "indent-with-non-tab",
+    indent_with_non_tab,
+    "In projects indented with tabs, an indent of as many spaces as a tab \
+     is wide should be a tab. This is the `indent-with-non-tab` class of \
+     git's `core.whitespace`, which is not enabled by default. The fix \
+     replaces the spaces with tabs, using `tabwidth` (8 by default).",
+    "if (x) {\n        return;\n}\n",
+    "if (x) {\n\treturn;\n}\n"
+);
+indent_rule!(
+    TabInIndent,
+    "tab-in-indent",
+    tab_in_indent,
+    "In projects indented with spaces, a tab in the indent is rendered with \
+     a different width by different tools. This is the `tab-in-indent` class \
+     of git's `core.whitespace`, which is not enabled by default. The fix \
+     expands tabs to `tabwidth` spaces (8 by default).",
+    "def f():\n\treturn 1\n",
+    "def f():\n        return 1\n"
+);
+
+/// `incomplete-line`.
+struct MissingNewline;
+
+impl Rule for MissingNewline {
+    fn id(&self) -> &str {
+        IssueType::MissingNewline.id()
+    }
+
+    fn description(&self) -> &str {
+        IssueType::MissingNewline.message()
+    }
+
+    fn explanation(&self) -> &str {
+        "A text file should end with a newline, otherwise its last line is \
+         incomplete: tools like `cat` or `wc -l` handle it poorly, and appending \
+         a line later changes the last one in diffs, which git marks with \
+         \"No newline at end of file\". This is the `incomplete-line` class of \
+         git's `core.whitespace`. The fix adds the newline."
+    }
+
+    fn example(&self) -> Option<Example<'_>> {
+        Some(Example {
+            bad: "first line\nlast line",
+            good: "first line\nlast line\n",
+            whitespace: "",
+        })
+    }
+
+    fn enabled(&self, ws: &WsRule) -> bool {
+        ws.missing_newline
+    }
+
+    fn line_ends_only(&self) -> bool {
+        true
+    }
+
+    fn check_file(&self, file: &FileInfo<'_>, _ws: &WsRule, reporter: &mut Reporter<'_>) {
+        if !file.ends_with_newline {
+            reporter.report(file.lines);
+        }
+    }
+
+    fn fixable(&self) -> bool {
+        true
+    }
+
+    fn fix_end(&self, end: &mut FileEnd, _ws: &WsRule) {
+        end.newline = true;
+    }
+}
+
+/// CRLF line endings, unless `cr-at-eol` is set.
+struct CrlfLineEnding;
+
+impl Rule for CrlfLineEnding {
+    fn id(&self) -> &str {
+        IssueType::CrlfLineEnding.id()
+    }
+
+    fn description(&self) -> &str {
+        IssueType::CrlfLineEnding.message()
+    }
+
+    fn explanation(&self) -> &str {
+        "Lines ending with CRLF, as written by some Windows tools, mix badly \
+         with lines ending with LF alone, and each CR shows up as trailing \
+         whitespace in diffs. Set `cr-at-eol` in `core.whitespace` or the \
+         `whitespace` attribute to accept CRLF line endings, or use the `eol` \
+         attribute to have git convert them. The fix converts them to LF."
+    }
+
+    fn example(&self) -> Option<Example<'_>> {
+        Some(Example {
+            bad: "first\r\nsecond\r\n",
+            good: "first\nsecond\n",
+            whitespace: "",
+        })
+    }
+
+    fn enabled(&self, ws: &WsRule) -> bool {
+        ws.crlf
+    }
+
+    fn line_ends_only(&self) -> bool {
+        true
+    }
+
+    fn check_file(&self, file: &FileInfo<'_>, _ws: &WsRule, reporter: &mut Reporter<'_>) {
+        // Once a line ends with CRLF, every CR is taken as part of one.
+        if file.crlf {
+            for &line in file.cr_lines {
+                reporter.report(line);
+            }
+        }
+    }
+
+    fn fixable(&self) -> bool {
+        true
+    }
+
+    fn fix_line(&self, line: &mut FixedLine, _ws: &WsRule) {
+        if line.newline {
+            line.cr = false;
+        }
+    }
+}
+
+/// `blank-at-eof`.
+struct MultipleBlankLinesEof;
+
+impl Rule for MultipleBlankLinesEof {
+    fn id(&self) -> &str {
+        IssueType::MultipleBlankLinesEof.id()
+    }
+
+    fn description(&self) -> &str {
+        IssueType::MultipleBlankLinesEof.message()
+    }
+
+    fn explanation(&self) -> &str {
+        "Blank lines at the end of a file are invisible and only add noise to \
+         diffs. This is the `blank-at-eof` class of git's `core.whitespace`. \
+         The fix removes them, keeping the newline of the last line."
+    }
+
+    fn example(&self) -> Option<Example<'_>> {
+        Some(Example {
+            bad: "end\n\n\n",
+            good: "end\n",
+            whitespace: "",
+        })
+    }
+
+    fn enabled(&self, ws: &WsRule) -> bool {
+        ws.blank_at_eof
+    }
+
+    fn line_ends_only(&self) -> bool {
+        true
+    }
+
+    fn check_file(&self, file: &FileInfo<'_>, _ws: &WsRule, reporter: &mut Reporter<'_>) {
+        if file.trailing_newlines > 1 {
+            reporter.report(file.lines);
+        }
+    }
+
+    fn fixable(&self) -> bool {
+        true
+    }
+
+    fn fix_end(&self, end: &mut FileEnd, _ws: &WsRule) {
+        // A file of nothing but blank lines keeps the first of them.
+        while end.lines.len() > 1 && end.lines.last().is_some_and(|l| l.trim_end().is_empty()) {
+            end.lines.pop();
+        }
+    }
+}
+
+/// Suppression directives that suppress nothing, reported by the registry
+/// itself once all other rules were checked.
+struct UnusedSuppression;
+
+impl Rule for UnusedSuppression {
+    fn id(&self) -> &str {
+        IssueType::UnusedSuppression.id()
+    }
+
+    fn description(&self) -> &str {
+        IssueType::UnusedSuppression.message()
+    }
+
+    fn explanation(&self) -> &str {
+        "A `clean:ignore-next-line`, `clean:disable` or `clean:disable-file` \
+         directive that suppresses no issue is left over from a fixed issue, \
+         or names the wrong rule or line. Only directives of rules that are \
+         checked in the file are reported. Remove the directive, there is no \
+         automatic fix."
+    }
+
+    fn example(&self) -> Option<Example<'_>> {
+        Some(Example {
+            bad: "# clean:ignore-next-line trailing_whitespace\nno trailing space\n",
+            good: "no trailing space\n",
+            whitespace: "",
+        })
+    }
+
+    fn line_ends_only(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,237 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! An on-disk cache of lint results, so that unchanged files are not linted
+//! again.
+//!
+//! Results are keyed by a hash of the file content, the version of clean,
+//! the configured rules applying to the file and the effective [`WsRule`],
+//! and stored as one small JSON file per key below `$XDG_CACHE_HOME/clean`.
+//! Errors are never fatal, a broken cache only makes clean lint files again.
+//!
+//! Reading an entry refreshes its modification time, and entries that were
+//! not used for [`MAX_AGE`] are removed, checked at most once per
+//! [`PRUNE_INTERVAL`].
+
+use anyhow::Result;
+use log::{debug, info};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+use xxhash_rust::xxh3::Xxh3;
+
+use clean::whitespace::WsRule;
+use clean::{Issue, IssueType, Registry, Severity};
+
+/// How long an entry is kept after it was last used.
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How often the cache is checked for entries to remove.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The file whose modification time is that of the last pruning.
+const PRUNE_STAMP: &str = ".pruned";
+
+/// An issue without the file name, which is not part of the key.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Entry {
+    #[serde(rename = "type")]
+    issue_type: IssueType,
+    line: Option<usize>,
+    severity: Severity,
+    /// Only stored if it differs from the message of the issue type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+pub struct Cache {
+    dir: PathBuf,
+    salt: String,
+}
+
+impl Cache {
+    /// Opens the cache in `$XDG_CACHE_HOME/clean`, falling back to
+    /// `~/.cache/clean`, or `None` if neither is known.
+    ///
+    /// `salt` is part of every key, it describes the configuration.
+    pub fn open(salt: &str) -> Option<Self> {
+        let base = match std::env::var_os("XDG_CACHE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+        };
+        Some(Cache {
+            dir: base.join("clean"),
+            salt: salt.to_string(),
+        })
+    }
+
+    fn key(&self, registry: &Registry, path: &str, content: &[u8], ws: &WsRule) -> String {
+        let mut hasher = Xxh3::new();
+        hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.update(b"\0");
+        hasher.update(self.salt.as_bytes());
+        hasher.update(b"\0");
+        // Rules may only apply to some paths, or be turned off there.
+        for rule in registry.rules_for(path) {
+            hasher.update(rule.id().as_bytes());
+            hasher.update(format!("{:?}", registry.severity(rule.id(), path)).as_bytes());
+            hasher.update(b"\0");
+        }
+        hasher.update(format!("{:?}", ws).as_bytes());
+        hasher.update(b"\0");
+        hasher.update(content);
+        format!("{:032x}", hasher.digest128())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(&key[..2]).join(&key[2..])
+    }
+
+    fn get(&self, key: &str, path: &str) -> Option<Vec<Issue>> {
+        let mut file = fs::File::open(self.entry_path(key)).ok()?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).ok()?;
+        // Keeps the entry from being pruned.
+        if let Err(e) = file.set_modified(SystemTime::now()) {
+            debug!("failed to touch cache entry {}: {}", key, e);
+        }
+        let entries: Vec<Entry> = match serde_json::from_slice(&data) {
+            Ok(entries) => entries,
+            Err(e) => {
+                debug!("invalid cache entry {}: {}", key, e);
+                return None;
+            }
+        };
+        Some(
+            entries
+                .into_iter()
+                .map(|e| Issue {
+                    message: e.message.or_else(|| Some(e.issue_type.message().into())),
+                    issue_type: e.issue_type,
+                    line: e.line,
+                    file: path.to_string(),
+                    severity: e.severity,
+                })
+                .collect(),
+        )
+    }
+
+    fn put(&self, key: &str, issues: &[Issue]) -> Result<()> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let entries: Vec<Entry> = issues
+            .iter()
+            .map(|i| Entry {
+                issue_type: i.issue_type.clone(),
+                line: i.line,
+                severity: i.severity,
+                message: i
+                    .message
+                    .clone()
+                    .filter(|m| m != i.issue_type.message()),
+            })
+            .collect();
+        let path = self.entry_path(key);
+        let dir = path.parent().unwrap();
+        fs::create_dir_all(dir)?;
+        // Other threads and processes may write the same entry concurrently.
+        let tmp = dir.join(format!(
+            ".{}.{}.{}",
+            &key[2..],
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&tmp, serde_json::to_vec(&entries)?)?;
+        if let Err(e) = fs::rename(&tmp, &path) {
+            let _ = fs::remove_file(&tmp);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Removes the entries that were not used for [`MAX_AGE`], unless that
+    /// was done less than [`PRUNE_INTERVAL`] ago.
+    pub fn prune(&self) {
+        let stamp = self.dir.join(PRUNE_STAMP);
+        let now = SystemTime::now();
+        let last = fs::metadata(&stamp).and_then(|m| m.modified());
+        if last.is_ok_and(|t| now.duration_since(t).is_ok_and(|d| d < PRUNE_INTERVAL)) {
+            return;
+        }
+        // Written first, so that concurrent runs do not prune too.
+        if let Err(e) = fs::create_dir_all(&self.dir).and_then(|_| fs::write(&stamp, "")) {
+            debug!("failed to write {}: {}", stamp.display(), e);
+            return;
+        }
+        let mut removed = 0;
+        for dir in read_dir(&self.dir) {
+            if !dir.is_dir() {
+                continue;
+            }
+            for entry in read_dir(&dir) {
+                let expired = fs::metadata(&entry)
+                    .and_then(|m| m.modified())
+                    .is_ok_and(|t| now.duration_since(t).is_ok_and(|d| d > MAX_AGE));
+                if expired && fs::remove_file(&entry).is_ok() {
+                    removed += 1;
+                }
+            }
+            // Only succeeds once the directory is empty.
+            let _ = fs::remove_dir(&dir);
+        }
+        debug!("removed {} expired cache entries", removed);
+    }
+
+    /// Removes all cached results.
+    pub fn clear(&self) -> Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                anyhow::bail!("failed to remove cache {}: {}", self.dir.display(), e)
+            }
+            _ => {
+                info!("removed cache {}", self.dir.display());
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Returns the paths in `dir`, or none if it cannot be read.
+fn read_dir(dir: &Path) -> Vec<PathBuf> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| Some(e.ok()?.path())).collect(),
+        Err(e) => {
+            debug!("failed to read {}: {}", dir.display(), e);
+            Vec::new()
+        }
+    }
+}
+
+/// Like [`Registry::lint`], but returns the cached result if there is one.
+///
+/// Only results of text files are cached, so that non-UTF-8 files are still
+/// reported as skipped. Failures of rules are not cached.
+pub fn lint(
+    cache: Option<&Cache>,
+    registry: &Registry,
+    path: &str,
+    content: &[u8],
+    ws: &WsRule,
+) -> clean::Result<Vec<Issue>> {
+    let Some(cache) = cache else {
+        return registry.lint(path, content, ws);
+    };
+    let key = cache.key(registry, path, content, ws);
+    if let Some(issues) = cache.get(&key, path) {
+        return Ok(issues);
+    }
+    let issues = registry.lint(path, content, ws)?;
+    if issues.is_empty() || std::str::from_utf8(content).is_ok() {
+        if let Err(e) = cache.put(&key, &issues) {
+            debug!("failed to write cache entry for '{}': {}", path, e);
+        }
+    }
+    Ok(issues)
+}
@@ -0,0 +1,120 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! The `rules` and `explain` commands, describing the rules of a registry.
+
+use anyhow::Result;
+use std::io::Write;
+
+use clean::{Registry, WsRule};
+
+use crate::exit::Failure;
+
+/// Prints a table of all rules with their default severity and whether they
+/// can be fixed.
+pub fn list(registry: &Registry, out: &mut dyn Write) -> Result<()> {
+    let width = registry.rules().map(|r| r.id().len()).max().unwrap_or(0);
+    writeln!(
+        out,
+        "{:<width$}  {:<8}  {:<7}  DESCRIPTION",
+        "RULE", "SEVERITY", "FIXABLE"
+    )?;
+    for rule in registry.rules() {
+        writeln!(
+            out,
+            "{:<width$}  {:<8}  {:<7}  {}",
+            rule.id(),
+            rule.default_severity().to_string(),
+            yes_no(rule.fixable()),
+            rule.description()
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Prints the explanation of the rule `id` and its example.
+pub fn explain(registry: &Registry, id: &str, out: &mut dyn Write) -> Result<()> {
+    let Some(rule) = registry.get(id) else {
+        return Err(Failure::Usage(format!("unknown rule '{}', see `clean rules`", id)).into());
+    };
+    writeln!(out, "{}: {}\n", rule.id(), rule.description())?;
+    writeln!(out, "Default severity: {}", rule.default_severity())?;
+    writeln!(out, "Fixable: {}", yes_no(rule.fixable()))?;
+    if !rule.enabled(&WsRule::default()) {
+        match rule.example() {
+            Some(example) if !example.whitespace.is_empty() => writeln!(
+                out,
+                "Checked only with `{}` in core.whitespace or the whitespace attribute",
+                example.whitespace
+            )?,
+            _ => writeln!(out, "Not checked by default")?,
+        }
+    }
+    if !rule.explanation().is_empty() {
+        writeln!(out)?;
+        for line in wrap(rule.explanation(), 72) {
+            writeln!(out, "{}", line)?;
+        }
+    }
+    if let Some(example) = rule.example() {
+        writeln!(
+            out,
+            "\nExample, where `$` ends a line, `^I` is a tab and `^M` a CR:"
+        )?;
+        writeln!(out, "\nBad:\n")?;
+        write_visible(out, example.bad)?;
+        writeln!(out, "\nGood:\n")?;
+        write_visible(out, example.good)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Writes `text` indented, with its whitespace made visible like `cat -A`
+/// does.
+fn write_visible(out: &mut dyn Write, text: &str) -> Result<()> {
+    let (body, newline) = match text.strip_suffix('\n') {
+        Some(body) => (body, true),
+        None => (text, false),
+    };
+    let mut lines = body.split('\n').peekable();
+    while let Some(line) = lines.next() {
+        let line = line.replace('\t', "^I").replace('\r', "^M");
+        let end = if newline || lines.peek().is_some() {
+            "$"
+        } else {
+            ""
+        };
+        writeln!(out, "    {}{}", line, end)?;
+    }
+    Ok(())
+}
+
+/// Splits `text` into lines of at most `width` characters, at spaces.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
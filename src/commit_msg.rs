@@ -0,0 +1,137 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Linting commit messages and tag annotations, for use in a `commit-msg` hook.
+
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+use clean::git;
+use clean::whitespace::{RuleResolver, WsRule};
+use clean::{Issue, Registry};
+
+use crate::exit::Failure;
+use crate::rewrite;
+
+/// The comment prefix git uses for a commit message, see `core.commentChar`.
+fn comment_prefix() -> String {
+    let dir = Path::new(".");
+    let configured = git::config_get(dir, "core.commentString")
+        .ok()
+        .flatten()
+        .or_else(|| git::config_get(dir, "core.commentChar").ok().flatten());
+    match configured {
+        // With "auto", git picks a character not used in the message when
+        // it prepares the template, which is not known here.
+        Some(c) if !c.is_empty() && c != "auto" => c,
+        _ => "#".to_string(),
+    }
+}
+
+/// A commit message split into the part git keeps and the part it strips.
+struct Message<'a> {
+    lines: Vec<&'a str>,
+    comment: String,
+    /// Index of the first line after the message: trailing blank lines,
+    /// comments and everything below the scissors line.
+    tail_start: usize,
+    /// Whether the tail contains lines git strips, rather than just blank lines.
+    tail_stripped: bool,
+}
+
+impl<'a> Message<'a> {
+    fn parse(content: &'a str, comment: String) -> Self {
+        let lines: Vec<&str> = content.split('\n').collect();
+        let scissors = format!("{} ------------------------ >8 ------------------------", comment);
+        let cut = lines
+            .iter()
+            .position(|l| l.trim_end_matches('\r') == scissors)
+            .unwrap_or(lines.len());
+        let tail_start = lines[..cut]
+            .iter()
+            .rposition(|l| !l.trim().is_empty() && !l.starts_with(&comment))
+            .map_or(0, |i| i + 1);
+        let tail_stripped = cut < lines.len()
+            || lines[tail_start..].iter().any(|l| l.starts_with(&comment));
+        Message {
+            lines,
+            comment,
+            tail_start,
+            tail_stripped,
+        }
+    }
+
+    fn is_comment(&self, line: &str) -> bool {
+        line.starts_with(&self.comment)
+    }
+
+    /// The message with comment lines blanked out, keeping line numbers.
+    fn lintable(&self) -> String {
+        let mut text: Vec<&str> = self.lines[..self.tail_start]
+            .iter()
+            .map(|l| if self.is_comment(l) { "" } else { l })
+            .collect();
+        if self.tail_stripped {
+            // The message ends where the stripped part starts.
+            text.push("");
+        } else {
+            text.extend_from_slice(&self.lines[self.tail_start..]);
+        }
+        text.join("\n")
+    }
+
+    /// The message at `path` with the issues of `registry` fixed, leaving
+    /// comment lines and the stripped part untouched.
+    fn fixed(&self, registry: &Registry, path: &str, ws: &WsRule) -> String {
+        let fixed = registry.fix(path, &self.lintable(), ws);
+        let mut lines: Vec<String> = fixed.split('\n').map(str::to_string).collect();
+        // Fixes keep the line numbers of the message, blanked out comment
+        // lines are put back in place.
+        for (line, original) in lines.iter_mut().zip(&self.lines[..self.tail_start]) {
+            if self.is_comment(original) {
+                *line = original.to_string();
+            }
+        }
+        if self.tail_stripped {
+            // The line after the last newline, replaced by the stripped part.
+            lines.pop();
+            lines.extend(self.lines[self.tail_start..].iter().map(|l| l.to_string()));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Lints the commit message in `path` with the rules of `registry` and
+/// `core.whitespace`, or fixes it in place if `fix` is set.
+///
+/// Returns the issues of the message, those left after fixing it with `fix`.
+pub fn run(registry: &Registry, path: &Path, fix: bool) -> Result<Vec<Issue>> {
+    let content = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => {
+            let message = format!("failed to read commit message {}: {}", path.display(), e);
+            return Err(Failure::Io(message).into());
+        }
+    };
+    let ws = RuleResolver::new(Path::new("."))?.core();
+    let name = path.to_string_lossy();
+    let mut message = Message::parse(&content, comment_prefix());
+    let fixed;
+    if fix {
+        fixed = message.fixed(registry, &name, &ws);
+        if fixed != content {
+            let target = rewrite::Target {
+                path: path.to_path_buf(),
+                in_place: false,
+            };
+            if let Err(e) = rewrite::replace(&target, |out| out.write_all(fixed.as_bytes())) {
+                let message = format!("failed to fix commit message {}: {}", path.display(), e);
+                return Err(Failure::Fix(message).into());
+            }
+        }
+        message = Message::parse(&fixed, message.comment);
+    }
+    Ok(registry.lint(&name, message.lintable().as_bytes(), &ws)?)
+}
@@ -0,0 +1,296 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! The configuration file, `.clean.toml`.
+//!
+//! It defines additional [`RegexRule`]s and [`PluginRule`]s, and
+//! [`Override`]s of the severities of rules:
+//!
+//! ```toml
+//! [[rules]]
+//! id = "no_todo"
+//! message = "TODO left in code"
+//! pattern = "TODO"
+//! severity = "warning"
+//! files = ["*.rs"]
+//!
+//! [[rules]]
+//! id = "nbsp"
+//! message = "Non-breaking space"
+//! pattern = "\u00A0"
+//! replacement = " "
+//!
+//! [[plugins]]
+//! id = "shell_quotes"
+//! message = "Unquoted variable"
+//! command = ["python3", "scripts/quotes.py"]
+//! files = ["*.sh"]
+//! input = "path"
+//! timeout = 10
+//! fixable = true
+//! settings = { style = "double" }
+//!
+//! [[overrides]]
+//! files = ["docs/**"]
+//! rules.trailing_whitespace = "off"
+//! rules.no_todo = "info"
+//! ```
+//!
+//! Relative programs of plugin commands with a `/`, like the one above, are
+//! relative to the directory of the configuration file.
+//!
+//! Severities are `error`, `warning`, `info` or `off`. Overrides without
+//! `files` apply to all files, and later overrides take precedence over
+//! earlier ones.
+//!
+//! The glob patterns of `files` are matched against paths relative to the
+//! directory of the configuration file, wherever `clean` is run from.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::plugin::{self, Input};
+use crate::rule::{Override, Severity};
+use crate::{Error, PluginRule, RegexRule, Registry, Result};
+
+/// The name of the configuration file looked up by [`Config::find`].
+pub const FILE_NAME: &str = ".clean.toml";
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    #[serde(default)]
+    rules: Vec<RawRule>,
+    #[serde(default)]
+    plugins: Vec<RawPlugin>,
+    #[serde(default)]
+    overrides: Vec<RawOverride>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    id: String,
+    message: String,
+    pattern: String,
+    #[serde(default = "default_severity")]
+    severity: Severity,
+    #[serde(default)]
+    files: Vec<String>,
+    replacement: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPlugin {
+    id: String,
+    message: String,
+    command: Vec<String>,
+    #[serde(default = "default_severity")]
+    severity: Severity,
+    #[serde(default)]
+    files: Vec<String>,
+    #[serde(default)]
+    input: Input,
+    /// In seconds.
+    timeout: Option<u64>,
+    #[serde(default)]
+    fixable: bool,
+    settings: Option<toml::Table>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawOverride {
+    #[serde(default)]
+    files: Vec<String>,
+    #[serde(default)]
+    rules: BTreeMap<String, Level>,
+}
+
+/// A severity, or `off`.
+#[derive(Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Level {
+    Off,
+    Info,
+    Warning,
+    Error,
+}
+
+impl Level {
+    fn severity(self) -> Option<Severity> {
+        match self {
+            Level::Off => None,
+            Level::Info => Some(Severity::Info),
+            Level::Warning => Some(Severity::Warning),
+            Level::Error => Some(Severity::Error),
+        }
+    }
+}
+
+fn default_severity() -> Severity {
+    Severity::Error
+}
+
+/// A loaded and validated configuration.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    rules: Vec<RegexRule>,
+    plugins: Vec<PluginRule>,
+    overrides: Vec<Override>,
+    /// The canonical directory of the configuration file.
+    root: Option<PathBuf>,
+    /// The text of the configuration file.
+    source: String,
+}
+
+impl Config {
+    /// Returns the configuration file in `dir` or the closest of its
+    /// ancestors, if there is one.
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|d| d.join(FILE_NAME))
+            .find(|p| p.is_file())
+    }
+
+    /// Reads and validates the configuration file at `path`.
+    ///
+    /// Fails with [`Error::Config`] if it cannot be read, is not valid TOML,
+    /// defines an invalid rule, or overrides an unknown one.
+    pub fn load(path: &Path) -> Result<Config> {
+        let config_error = |message: String| Error::Config {
+            path: path.to_path_buf(),
+            message,
+        };
+        let text = fs::read_to_string(path).map_err(|e| config_error(e.to_string()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut config = Config::parse(&text, dir).map_err(config_error)?;
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        config.root = Some(fs::canonicalize(dir).map_err(|e| config_error(e.to_string()))?);
+        config.source = text;
+        Ok(config)
+    }
+
+    fn parse(text: &str, dir: &Path) -> Result<Config, String> {
+        let raw: RawConfig = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut ids: Vec<String> = Vec::new();
+        for id in raw
+            .rules
+            .iter()
+            .map(|r| &r.id)
+            .chain(raw.plugins.iter().map(|p| &p.id))
+        {
+            if Registry::builtin().get(id).is_some() {
+                return Err(format!("rule '{}' is built in", id));
+            }
+            if ids.contains(id) {
+                return Err(format!("rule '{}' is defined twice", id));
+            }
+            ids.push(id.clone());
+        }
+        let mut rules = Vec::new();
+        for raw in raw.rules {
+            let rule = RegexRule::new(&raw.id, &raw.message, &raw.pattern)
+                .and_then(|r| r.files(raw.files))
+                .map_err(|e| e.to_string())?
+                .severity(raw.severity)
+                .replacement(raw.replacement);
+            rules.push(rule);
+        }
+        let mut plugins = Vec::new();
+        for mut raw in raw.plugins {
+            if let Some(program) = raw.command.first_mut() {
+                if program.contains('/') && Path::new(program).is_relative() {
+                    *program = dir.join(&*program).to_string_lossy().into_owned();
+                }
+            }
+            let settings = serde_json::to_value(raw.settings.unwrap_or_default())
+                .map_err(|e| e.to_string())?;
+            let timeout = raw
+                .timeout
+                .map_or(plugin::DEFAULT_TIMEOUT, Duration::from_secs);
+            let plugin = PluginRule::new(&raw.id, &raw.message, raw.command)
+                .and_then(|p| p.files(raw.files))
+                .map_err(|e| e.to_string())?
+                .severity(raw.severity)
+                .input(raw.input)
+                .settings(settings)
+                .timeout(timeout)
+                .fixable(raw.fixable);
+            plugins.push(plugin);
+        }
+        let mut overrides = Vec::new();
+        for raw in raw.overrides {
+            let mut o = Override::new(raw.files).map_err(|e| e.to_string())?;
+            for (id, level) in raw.rules {
+                if Registry::builtin().get(&id).is_none() && !ids.contains(&id) {
+                    return Err(format!("unknown rule '{}' in overrides", id));
+                }
+                o = o.rule(&id, level.severity());
+            }
+            overrides.push(o);
+        }
+        Ok(Config {
+            rules,
+            plugins,
+            overrides,
+            root: None,
+            source: String::new(),
+        })
+    }
+
+    /// Returns the regex rules defined in the configuration.
+    pub fn rules(&self) -> &[RegexRule] {
+        &self.rules
+    }
+
+    /// Returns the plugins defined in the configuration.
+    pub fn plugins(&self) -> &[PluginRule] {
+        &self.plugins
+    }
+
+    /// Returns the overrides of severities, in order.
+    pub fn overrides(&self) -> &[Override] {
+        &self.overrides
+    }
+
+    /// Returns the directory of the configuration file, which the glob
+    /// patterns of files in it are relative to.
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// Returns the text of the configuration file, empty without one.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns a registry with the built-in rules and those of the
+    /// configuration, and its overrides, matching glob patterns of files
+    /// relative to the directory of the configuration file.
+    pub fn registry(&self) -> Registry {
+        let mut registry = Registry::default();
+        if let Some(root) = &self.root {
+            registry.root(root);
+        }
+        for rule in &self.rules {
+            registry.register(Arc::new(rule.clone()));
+        }
+        for plugin in &self.plugins {
+            registry.register(Arc::new(plugin.clone()));
+        }
+        for o in &self.overrides {
+            registry.add_override(o.clone());
+        }
+        registry
+    }
+}
@@ -0,0 +1,129 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Unified diffs of fixes, in the format of `git diff`, so that `git apply`
+//! accepts them.
+
+use std::fmt::Write;
+
+/// Lines of context around changes.
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Returns the diff from `old` to `new`, the content of the file at `path`
+/// before and after fixing it, or `None` if they are equal.
+///
+/// Lines between the common prefix and suffix are paired by position rather
+/// than with a minimal diff. Fixes change lines in place or remove lines at
+/// the end, so this keeps diffing linear even if every line changes, as for
+/// CRLF line endings.
+pub fn unified(path: &str, old: &str, new: &str) -> Option<String> {
+    if old == new {
+        return None;
+    }
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = ops(&old, &new);
+
+    let path = path.strip_prefix("./").unwrap_or(path);
+    let mut out = String::new();
+    let _ = write!(out, "diff --git a/{0} b/{0}\n--- a/{0}\n+++ b/{0}\n", path);
+    // The position in both files before each operation.
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut i, mut j) = (0, 0);
+    for op in &ops {
+        positions.push((i, j));
+        match op {
+            Op::Equal => (i, j) = (i + 1, j + 1),
+            Op::Delete => i += 1,
+            Op::Insert => j += 1,
+        }
+    }
+    positions.push((i, j));
+
+    let changes: Vec<usize> = (0..ops.len()).filter(|&k| ops[k] != Op::Equal).collect();
+    let mut k = 0;
+    while k < changes.len() {
+        // Changes closer than twice the context share a hunk.
+        let mut last = k;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= 2 * CONTEXT + 1 {
+            last += 1;
+        }
+        let start = changes[k].saturating_sub(CONTEXT);
+        let end = (changes[last] + CONTEXT + 1).min(ops.len());
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        let _ = writeln!(
+            out,
+            "@@ -{} +{} @@",
+            range(old_start, old_end - old_start),
+            range(new_start, new_end - new_start)
+        );
+        for (op, &(i, j)) in ops[start..end].iter().zip(&positions[start..end]) {
+            let (sign, line) = match op {
+                Op::Equal => (' ', old[i]),
+                Op::Delete => ('-', old[i]),
+                Op::Insert => ('+', new[j]),
+            };
+            out.push(sign);
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+        k = last + 1;
+    }
+    Some(out)
+}
+
+/// Returns the operations turning the lines `old` into `new`.
+fn ops(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut ops = vec![Op::Equal; prefix];
+    let paired = old_mid.len().min(new_mid.len());
+    let mut k = 0;
+    while k < paired {
+        if old_mid[k] == new_mid[k] {
+            ops.push(Op::Equal);
+            k += 1;
+            continue;
+        }
+        // A run of changed lines is shown as deleted, then inserted.
+        let run = (k..paired)
+            .take_while(|&r| old_mid[r] != new_mid[r])
+            .count();
+        ops.extend(std::iter::repeat_n(Op::Delete, run));
+        ops.extend(std::iter::repeat_n(Op::Insert, run));
+        k += run;
+    }
+    ops.extend(std::iter::repeat_n(Op::Delete, old_mid.len() - paired));
+    ops.extend(std::iter::repeat_n(Op::Insert, new_mid.len() - paired));
+    ops.extend(std::iter::repeat_n(Op::Equal, suffix));
+    ops
+}
+
+/// Formats the range of a hunk starting after line `start`, 0-based, with
+/// `count` lines, leaving out a count of 1 like git does.
+fn range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
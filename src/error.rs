@@ -0,0 +1,99 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! The error type of the library.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// An error while linting or fixing.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file or a child process failed.
+    Io(io::Error),
+    /// A `git` command failed or printed something unexpected.
+    Git {
+        /// The command, such as `git ls-files`.
+        command: String,
+        /// What went wrong, usually the stderr of the command.
+        message: String,
+    },
+    /// An ignore pattern is not a valid glob.
+    Pattern(glob::PatternError),
+    /// A whitespace rule from `core.whitespace` or the `whitespace`
+    /// attribute is invalid.
+    WhitespaceRule(String),
+    /// A file is not valid UTF-8 text.
+    NotUtf8(PathBuf),
+    /// A rule is defined with an invalid pattern or id.
+    InvalidRule {
+        /// The id of the rule.
+        id: String,
+        /// What is wrong with it.
+        message: String,
+    },
+    /// A rule failed to check a file, such as a plugin that crashed.
+    RuleFailed {
+        /// The id of the rule.
+        id: String,
+        /// What went wrong.
+        message: String,
+    },
+    /// A configuration file cannot be read or is invalid.
+    Config {
+        /// The path of the configuration file.
+        path: PathBuf,
+        /// What is wrong with it.
+        message: String,
+    },
+}
+
+/// A `Result` with [`Error`] as the error type.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Git { command, message } => write!(f, "`{}` failed: {}", command, message),
+            Error::Pattern(e) => write!(f, "invalid glob pattern: {}", e),
+            Error::WhitespaceRule(message) => write!(f, "invalid whitespace rule: {}", message),
+            Error::InvalidRule { id, message } => write!(f, "invalid rule '{}': {}", id, message),
+            Error::RuleFailed { id, message } => write!(f, "rule '{}' failed: {}", id, message),
+            Error::Config { path, message } => {
+                write!(f, "invalid config {}: {}", path.display(), message)
+            }
+            Error::NotUtf8(path) => {
+                write!(
+                    f,
+                    "file '{}' is not a valid UTF-8 text file",
+                    path.display()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Pattern(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<glob::PatternError> for Error {
+    fn from(e: glob::PatternError) -> Self {
+        Error::Pattern(e)
+    }
+}
@@ -0,0 +1,80 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! The exit status of the command line.
+//!
+//! | Status | Meaning                                           |
+//! |--------|---------------------------------------------------|
+//! | 0      | No issues found                                   |
+//! | 1      | Issues found                                      |
+//! | 2      | Invalid arguments or configuration                |
+//! | 3      | Reading or writing files, git or a plugin failed  |
+//! | 4      | Some files could not be fixed or restored         |
+
+use std::fmt;
+use std::process::ExitCode;
+
+/// Why a command failed, which decides its exit status.
+#[derive(Debug)]
+pub enum Failure {
+    /// Issues were found, they are in the report already.
+    Issues,
+    /// Invalid arguments or configuration.
+    Usage(String),
+    /// Reading or writing a file, or running git or a plugin failed.
+    Io(String),
+    /// Some files could not be fixed or restored.
+    Fix(String),
+}
+
+impl Failure {
+    pub fn code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            Failure::Issues => 1,
+            Failure::Usage(_) => 2,
+            Failure::Io(_) => 3,
+            Failure::Fix(_) => 4,
+        })
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Issues => f.write_str("issues found"),
+            Failure::Usage(message) | Failure::Io(message) | Failure::Fix(message) => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Failure {}
+
+impl From<anyhow::Error> for Failure {
+    /// Returns the failure an error was raised with, or classifies it by its
+    /// causes. Errors of unknown causes are taken as I/O failures.
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<Failure>() {
+            Ok(failure) => return failure,
+            Err(e) => e,
+        };
+        let message = format!("{:#}", e);
+        for cause in e.chain() {
+            if cause.is::<glob::PatternError>() {
+                return Failure::Usage(message);
+            }
+            if let Some(e) = cause.downcast_ref::<clean::Error>() {
+                return match e {
+                    clean::Error::Pattern(_)
+                    | clean::Error::WhitespaceRule(_)
+                    | clean::Error::InvalidRule { .. }
+                    | clean::Error::Config { .. } => Failure::Usage(message),
+                    _ => Failure::Io(message),
+                };
+            }
+        }
+        Failure::Io(message)
+    }
+}
@@ -0,0 +1,217 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Git long-running filter process protocol.
+//!
+//! See "Long Running Filter Process" in gitattributes(5). Configure it with:
+//!
+//! ```sh
+//! git config filter.clean.process "clean filter-process"
+//! echo "* filter=clean" >> .gitattributes
+//! ```
+
+use anyhow::Result;
+use log::warn;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use clean::whitespace::RuleResolver;
+use clean::{should_ignore, Registry};
+
+/// Maximum payload size of a single pkt-line.
+const MAX_PACKET_DATA: usize = 65516;
+
+enum Packet {
+    Flush,
+    Data(Vec<u8>),
+}
+
+fn read_packet(input: &mut impl BufRead) -> Result<Option<Packet>> {
+    let mut len = [0u8; 4];
+    if input.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    input.read_exact(&mut len)?;
+    let len = std::str::from_utf8(&len)
+        .ok()
+        .and_then(|l| usize::from_str_radix(l, 16).ok())
+        .ok_or_else(|| anyhow::anyhow!("invalid pkt-line length: {:?}", len))?;
+    match len {
+        0 => Ok(Some(Packet::Flush)),
+        1..=4 => anyhow::bail!("invalid pkt-line length: {}", len),
+        _ => {
+            let mut data = vec![0u8; len - 4];
+            input.read_exact(&mut data)?;
+            Ok(Some(Packet::Data(data)))
+        }
+    }
+}
+
+fn expect_packet(input: &mut impl BufRead) -> Result<Packet> {
+    match read_packet(input)? {
+        Some(p) => Ok(p),
+        None => anyhow::bail!("unexpected end of input in filter protocol"),
+    }
+}
+
+/// Reads text packets up to the next flush packet.
+fn read_text_list(input: &mut impl BufRead) -> Result<Option<Vec<String>>> {
+    let mut list = Vec::new();
+    let mut first = true;
+    loop {
+        let packet = if first {
+            match read_packet(input)? {
+                Some(p) => p,
+                None => return Ok(None),
+            }
+        } else {
+            expect_packet(input)?
+        };
+        first = false;
+        match packet {
+            Packet::Flush => return Ok(Some(list)),
+            Packet::Data(data) => {
+                let line = String::from_utf8_lossy(&data);
+                list.push(line.strip_suffix('\n').unwrap_or(&line).to_string());
+            }
+        }
+    }
+}
+
+/// Reads binary packets up to the next flush packet.
+fn read_content(input: &mut impl BufRead) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    loop {
+        match expect_packet(input)? {
+            Packet::Flush => return Ok(content),
+            Packet::Data(data) => content.extend_from_slice(&data),
+        }
+    }
+}
+
+fn write_packet(output: &mut impl Write, data: &[u8]) -> Result<()> {
+    write!(output, "{:04x}", data.len() + 4)?;
+    output.write_all(data)?;
+    Ok(())
+}
+
+fn write_flush(output: &mut impl Write) -> Result<()> {
+    output.write_all(b"0000")?;
+    Ok(())
+}
+
+fn write_text(output: &mut impl Write, line: &str) -> Result<()> {
+    write_packet(output, format!("{}\n", line).as_bytes())
+}
+
+fn write_content(output: &mut impl Write, content: &[u8]) -> Result<()> {
+    for chunk in content.chunks(MAX_PACKET_DATA) {
+        write_packet(output, chunk)?;
+    }
+    write_flush(output)
+}
+
+/// Answers a request with an error, without content.
+fn write_error(output: &mut impl Write) -> Result<()> {
+    write_text(output, "status=error")?;
+    write_flush(output)?;
+    output.flush()?;
+    Ok(())
+}
+
+fn handshake(input: &mut impl BufRead, output: &mut impl Write) -> Result<()> {
+    let welcome = read_text_list(input)?.unwrap_or_default();
+    if welcome.first().map(String::as_str) != Some("git-filter-client") {
+        anyhow::bail!("unexpected filter protocol welcome: {:?}", welcome);
+    }
+    if !welcome.iter().any(|l| l == "version=2") {
+        anyhow::bail!("unsupported filter protocol versions: {:?}", welcome);
+    }
+    write_text(output, "git-filter-server")?;
+    write_text(output, "version=2")?;
+    write_flush(output)?;
+    output.flush()?;
+
+    let capabilities = read_text_list(input)?.unwrap_or_default();
+    if !capabilities.iter().any(|c| c == "capability=clean") {
+        anyhow::bail!("git does not support the clean capability");
+    }
+    write_text(output, "capability=clean")?;
+    write_flush(output)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Normalizes blob content for `pathname` according to the ignore rules and
+/// gitattributes.
+fn clean_content(
+    registry: &Registry,
+    pathname: &str,
+    content: Vec<u8>,
+    ignores: &[String],
+    resolver: &mut RuleResolver,
+) -> Result<Vec<u8>> {
+    if should_ignore(pathname, ignores)? {
+        return Ok(content);
+    }
+    let Some(ws) = resolver.rule_for(pathname)? else {
+        return Ok(content);
+    };
+    // Clean content is passed through without being decoded.
+    if registry.lint(pathname, &content, &ws)?.is_empty() {
+        return Ok(content);
+    }
+    match String::from_utf8(content) {
+        Ok(text) => Ok(registry.fix(pathname, &text, &ws).into_bytes()),
+        Err(e) => {
+            warn!("file '{}' is not a valid UTF-8 text file, skipped", pathname);
+            Ok(e.into_bytes())
+        }
+    }
+}
+
+/// Serves git filter requests read from stdin until git closes the pipe.
+pub fn run(registry: &Registry, ignores: &[String]) -> Result<()> {
+    // Fail early on invalid patterns instead of on the first request.
+    should_ignore("", ignores)?;
+    // git runs filters from the top of the work tree, with paths relative to it.
+    let mut resolver = RuleResolver::new(Path::new("."))?;
+    let mut input = BufReader::new(io::stdin().lock());
+    let mut output = BufWriter::new(io::stdout().lock());
+    handshake(&mut input, &mut output)?;
+    while let Some(header) = read_text_list(&mut input)? {
+        let mut command = "";
+        let mut pathname = "";
+        for line in &header {
+            if let Some(v) = line.strip_prefix("command=") {
+                command = v;
+            } else if let Some(v) = line.strip_prefix("pathname=") {
+                pathname = v;
+            }
+        }
+        let content = read_content(&mut input)?;
+        if command != "clean" {
+            warn!("unsupported filter command '{}' for '{}'", command, pathname);
+            write_error(&mut output)?;
+            continue;
+        }
+        // A blob that can not be cleaned fails alone, git goes on with the
+        // others.
+        let cleaned = match clean_content(registry, pathname, content, ignores, &mut resolver) {
+            Ok(cleaned) => cleaned,
+            Err(e) => {
+                warn!("failed to clean '{}': {:#}", pathname, e);
+                write_error(&mut output)?;
+                continue;
+            }
+        };
+        write_text(&mut output, "status=success")?;
+        write_flush(&mut output)?;
+        write_content(&mut output, &cleaned)?;
+        // An empty list keeps the "success" status.
+        write_flush(&mut output)?;
+        output.flush()?;
+    }
+    Ok(())
+}
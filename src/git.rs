@@ -0,0 +1,313 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Helpers that query git by running the `git` command.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Output, Stdio};
+
+use crate::{Error, Result};
+
+pub fn is_git_repo(dir: &Path) -> bool {
+    dir.join(".git").exists()
+}
+
+fn git_error(command: &str, message: impl Into<String>) -> Error {
+    Error::Git {
+        command: command.to_string(),
+        message: message.into(),
+    }
+}
+
+fn check_status(what: &str, output: &Output) -> Result<()> {
+    if !output.status.success() {
+        let message = match output.status.code() {
+            Some(code) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                format!("exit with code={}: {}", code, stderr.trim())
+            }
+            _ => format!("killed by signal: {}", output.status.signal().unwrap()),
+        };
+        return Err(git_error(what, message));
+    }
+    Ok(())
+}
+
+pub fn git_tracked_files(dir: &Path) -> Result<HashSet<String>> {
+    let output = Command::new("git")
+        .arg("ls-files")
+        .current_dir(dir)
+        .output()?;
+    check_status("git ls-files", &output)?;
+    let files = String::from_utf8_lossy(&output.stdout);
+    Ok(files
+        .lines()
+        .map(|l| dir.join(l).to_string_lossy().to_string())
+        .collect())
+}
+
+/// Returns whether `dir` is inside a git work tree.
+pub fn inside_work_tree(dir: &Path) -> bool {
+    Command::new("git")
+        .arg("rev-parse")
+        .arg("--is-inside-work-tree")
+        .current_dir(dir)
+        .stderr(Stdio::null())
+        .output()
+        .map(|o| o.status.success() && o.stdout.starts_with(b"true"))
+        .unwrap_or(false)
+}
+
+/// Reads a single git configuration value, `None` if it is not set.
+pub fn config_get(dir: &Path, key: &str) -> Result<Option<String>> {
+    let output = Command::new("git")
+        .arg("config")
+        .arg("--get")
+        .arg(key)
+        .current_dir(dir)
+        .output()?;
+    // `git config --get` exits with 1 if the key is not set.
+    if output.status.code() == Some(1) {
+        return Ok(None);
+    }
+    check_status("git config", &output)?;
+    let value = String::from_utf8_lossy(&output.stdout);
+    Ok(Some(value.trim_end_matches('\n').to_string()))
+}
+
+/// The state of a gitattribute for a path, see gitattributes(5).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttrValue {
+    Set,
+    Unset,
+    Unspecified,
+    Value(String),
+}
+
+impl AttrValue {
+    fn parse(info: &str) -> Self {
+        match info {
+            "set" => AttrValue::Set,
+            "unset" => AttrValue::Unset,
+            "unspecified" => AttrValue::Unspecified,
+            v => AttrValue::Value(v.to_string()),
+        }
+    }
+}
+
+/// A long-running `git check-attr --stdin -z` process.
+///
+/// Paths are resolved relative to the directory the process was started in.
+pub struct GitAttributes {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    attrs: Vec<&'static str>,
+}
+
+impl GitAttributes {
+    /// Starts a checker for `attrs`, `None` if `dir` is not inside a git work tree.
+    pub fn spawn(dir: &Path, attrs: &[&'static str]) -> Result<Option<Self>> {
+        if !inside_work_tree(dir) {
+            return Ok(None);
+        }
+        let mut child = Command::new("git")
+            .arg("check-attr")
+            .arg("--stdin")
+            .arg("-z")
+            .args(attrs)
+            .current_dir(dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Some(GitAttributes {
+            child,
+            stdin,
+            stdout,
+            attrs: attrs.to_vec(),
+        }))
+    }
+
+    /// Looks up the attributes of `path`.
+    pub fn get(&mut self, path: &str) -> Result<HashMap<&'static str, AttrValue>> {
+        self.stdin.write_all(path.as_bytes())?;
+        self.stdin.write_all(b"\0")?;
+        self.stdin.flush()?;
+        read_attrs(&mut self.stdout, &self.attrs)
+    }
+
+    /// Looks up the attributes of all `paths`, in one round trip.
+    pub fn get_all(&mut self, paths: &[&str]) -> Result<Vec<HashMap<&'static str, AttrValue>>> {
+        let GitAttributes {
+            stdin,
+            stdout,
+            attrs,
+            ..
+        } = self;
+        std::thread::scope(|scope| {
+            // git stops reading paths while its output is not read, so they
+            // are written by another thread.
+            let writer = scope.spawn(move || -> std::io::Result<()> {
+                for path in paths {
+                    stdin.write_all(path.as_bytes())?;
+                    stdin.write_all(b"\0")?;
+                }
+                stdin.flush()
+            });
+            let values = paths
+                .iter()
+                .map(|_| read_attrs(stdout, attrs))
+                .collect::<Result<Vec<_>>>()?;
+            writer.join().expect("writing paths does not panic")?;
+            Ok(values)
+        })
+    }
+}
+
+/// Reads the attributes of one path from `git check-attr -z`.
+fn read_attrs(
+    stdout: &mut BufReader<ChildStdout>,
+    attrs: &[&'static str],
+) -> Result<HashMap<&'static str, AttrValue>> {
+    let mut result = HashMap::new();
+    // Each attribute is reported as "<path> NUL <attribute> NUL <info> NUL".
+    for _ in 0..attrs.len() {
+        let mut fields = Vec::with_capacity(3);
+        for _ in 0..3 {
+            let mut field = Vec::new();
+            if stdout.read_until(b'\0', &mut field)? == 0 {
+                return Err(git_error("git check-attr", "exited unexpectedly"));
+            }
+            field.pop();
+            fields.push(String::from_utf8_lossy(&field).to_string());
+        }
+        if let Some(attr) = attrs.iter().find(|a| **a == fields[1]) {
+            result.insert(*attr, AttrValue::parse(&fields[2]));
+        }
+    }
+    Ok(result)
+}
+
+impl Drop for GitAttributes {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Runs a git command in `dir` and returns its stdout.
+fn run(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git").args(args).current_dir(dir).output()?;
+    check_status(&format!("git {}", args.join(" ")), &output)?;
+    Ok(output.stdout)
+}
+
+fn split_nul(output: &[u8]) -> Vec<String> {
+    output
+        .split(|b| *b == b'\0')
+        .filter(|p| !p.is_empty())
+        .map(|p| String::from_utf8_lossy(p).to_string())
+        .collect()
+}
+
+/// Returns the top-level directory of the work tree containing `dir`.
+pub fn show_toplevel(dir: &Path) -> Result<PathBuf> {
+    let output = run(dir, &["rev-parse", "--show-toplevel"])?;
+    let top = String::from_utf8_lossy(&output);
+    Ok(PathBuf::from(top.trim_end_matches('\n')))
+}
+
+/// Resolves a path inside the git directory, such as `hooks`, honoring
+/// `core.hooksPath` and linked worktrees.
+pub fn git_path(dir: &Path, path: &str) -> Result<PathBuf> {
+    let output = run(dir, &["rev-parse", "--path-format=absolute", "--git-path", path])?;
+    let path = String::from_utf8_lossy(&output);
+    Ok(PathBuf::from(path.trim_end_matches('\n')))
+}
+
+/// Returns the paths of files added, copied, modified or renamed in the
+/// index, relative to the top-level directory.
+pub fn staged_files(dir: &Path) -> Result<Vec<String>> {
+    let output = run(
+        dir,
+        &["diff", "--cached", "--name-only", "-z", "--diff-filter=ACMR"],
+    )?;
+    Ok(split_nul(&output))
+}
+
+/// Returns the paths of files changed by commits in `revs`, relative to the
+/// top-level directory.
+pub fn changed_files(dir: &Path, revs: &[&str]) -> Result<Vec<String>> {
+    let mut args = vec!["log", "--format=", "--name-only", "-z", "--diff-filter=d"];
+    args.extend_from_slice(revs);
+    let mut files = split_nul(&run(dir, &args)?);
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// A long-running `git cat-file --batch` process.
+pub struct BlobReader {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl BlobReader {
+    pub fn spawn(dir: &Path) -> Result<Self> {
+        let mut child = Command::new("git")
+            .arg("cat-file")
+            .arg("--batch")
+            .current_dir(dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(BlobReader {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Reads the object named by `spec`, such as `:path` or `HEAD:path`.
+    ///
+    /// Returns `None` if the object is missing or is not a blob.
+    pub fn read(&mut self, spec: &str) -> Result<Option<Vec<u8>>> {
+        writeln!(self.stdin, "{}", spec)?;
+        self.stdin.flush()?;
+        let mut header = String::new();
+        if self.stdout.read_line(&mut header)? == 0 {
+            return Err(git_error("git cat-file", "exited unexpectedly"));
+        }
+        // "<oid> <type> <size>" or "<spec> missing"
+        let fields: Vec<&str> = header.trim_end().rsplitn(3, ' ').collect();
+        if fields.len() != 3 || fields[0] == "missing" {
+            return Ok(None);
+        }
+        let size: usize = fields[0]
+            .parse()
+            .map_err(|_| git_error("git cat-file", format!("invalid header {:?}", header)))?;
+        let mut content = vec![0u8; size + 1];
+        self.stdout.read_exact(&mut content)?;
+        content.pop();
+        if fields[1] != "blob" {
+            return Ok(None);
+        }
+        Ok(Some(content))
+    }
+}
+
+impl Drop for BlobReader {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
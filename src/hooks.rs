@@ -0,0 +1,183 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Installing `clean` into local git hooks.
+
+use anyhow::Result;
+use log::info;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use clean::git;
+
+const BEGIN_MARKER: &str = "# >>> clean >>>";
+const END_MARKER: &str = "# <<< clean <<<";
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Hook {
+    /// Lint staged content before committing
+    PreCommit,
+    /// Lint the commit message
+    CommitMsg,
+    /// Lint files changed by the commits being pushed
+    PrePush,
+}
+
+impl Hook {
+    fn name(self) -> &'static str {
+        match self {
+            Hook::PreCommit => "pre-commit",
+            Hook::CommitMsg => "commit-msg",
+            Hook::PrePush => "pre-push",
+        }
+    }
+
+    /// The commands running clean, as `"$clean_exe"`.
+    fn command(self) -> &'static str {
+        match self {
+            Hook::PreCommit => "\"$clean_exe\" --staged || exit $?",
+            Hook::CommitMsg => "\"$clean_exe\" commit-msg \"$1\" || exit $?",
+            // Both clean and the rest of the hook read the pushed refs from
+            // stdin, so they are saved to read them twice.
+            Hook::PrePush => {
+                "clean_refs=$(mktemp) || exit $?\n\
+                 cat > \"$clean_refs\"\n\
+                 \"$clean_exe\" pre-push \"$@\" < \"$clean_refs\"\n\
+                 clean_status=$?\n\
+                 exec < \"$clean_refs\"\n\
+                 rm -f \"$clean_refs\"\n\
+                 [ \"$clean_status\" -eq 0 ] || exit \"$clean_status\""
+            }
+        }
+    }
+
+    /// The name an existing hook in another language is moved to.
+    fn local_name(self) -> String {
+        format!("{}.local", self.name())
+    }
+
+    /// The rest of the shell script that runs the moved hook.
+    fn local_wrapper(self) -> String {
+        format!(
+            "#!/bin/sh\nexec \"$(dirname \"$0\")/{}\" \"$@\"\n",
+            self.local_name()
+        )
+    }
+}
+
+/// Shells whose scripts the POSIX shell block can be written into.
+const SHELLS: &[&str] = &["sh", "bash", "dash", "zsh"];
+
+/// Whether the script starting with `shebang` is run by one of [`SHELLS`].
+/// A script without a shebang line is run by the shell.
+fn is_shell_script(shebang: &str) -> bool {
+    let Some(command) = shebang.strip_prefix("#!") else {
+        return true;
+    };
+    let mut words = command.split_whitespace();
+    let mut interpreter = words.next().unwrap_or("");
+    if interpreter.rsplit('/').next() == Some("env") {
+        interpreter = words.find(|w| !w.starts_with('-')).unwrap_or("");
+    }
+    SHELLS.contains(&interpreter.rsplit('/').next().unwrap_or(""))
+}
+
+/// Quotes `s` for use in a POSIX shell script.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Removes the block between the markers, returning `None` if there is none.
+fn remove_block(script: &str) -> Option<String> {
+    let begin = script.find(BEGIN_MARKER)?;
+    let end = script[begin..].find(END_MARKER)? + begin + END_MARKER.len();
+    let end = if script[end..].starts_with('\n') {
+        end + 1
+    } else {
+        end
+    };
+    Some(format!("{}{}", &script[..begin], &script[end..]))
+}
+
+/// Writes `hook` into the hooks directory of the repository containing `dir`,
+/// chaining into an existing hook script, or removes it if `uninstall` is set.
+pub fn install(dir: &Path, hook: Hook, uninstall: bool) -> Result<()> {
+    let hooks_dir = git::git_path(dir, "hooks")?;
+    let path = hooks_dir.join(hook.name());
+    let existing = match fs::read_to_string(&path) {
+        Ok(s) => Some(s),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => anyhow::bail!("failed to read hook {}: {}", path.display(), e),
+    };
+    // Without our block, the script is either empty or someone else's hook.
+    let base = existing
+        .as_deref()
+        .map(|s| remove_block(s).unwrap_or_else(|| s.to_string()));
+
+    let local = hooks_dir.join(hook.local_name());
+    if uninstall {
+        let Some(base) = base else {
+            return Ok(());
+        };
+        if base == hook.local_wrapper() && local.exists() {
+            fs::rename(&local, &path)?;
+        } else if base
+            .lines()
+            .all(|l| l.trim().is_empty() || l.starts_with("#!"))
+        {
+            fs::remove_file(&path)?;
+        } else {
+            fs::write(&path, base)?;
+        }
+        info!("uninstalled {} hook from {}", hook.name(), path.display());
+        return Ok(());
+    }
+
+    // The binary may be moved or removed after installing the hook, clean
+    // is then looked up in PATH.
+    let exe = std::env::current_exe()?;
+    let block = format!(
+        "{}\nclean_exe={}\n[ -x \"$clean_exe\" ] || clean_exe=clean\n{}\n{}\n",
+        BEGIN_MARKER,
+        shell_quote(&exe.to_string_lossy()),
+        hook.command(),
+        END_MARKER
+    );
+    let base = match base {
+        Some(base) if !is_shell_script(base.lines().next().unwrap_or("")) => {
+            // The block can not be written into a script in another
+            // language, the script is moved and run by a shell script.
+            if local.exists() {
+                anyhow::bail!(
+                    "hook {} is not a shell script, and {} already exists",
+                    path.display(),
+                    local.display()
+                );
+            }
+            fs::rename(&path, &local)?;
+            info!("moved {} hook to {}", hook.name(), local.display());
+            Some(hook.local_wrapper())
+        }
+        base => base,
+    };
+    // The block goes right after the shebang, so that it also runs if the
+    // existing hook ends with `exec`.
+    let script = match base {
+        Some(base) if !base.trim().is_empty() => match base.split_once('\n') {
+            Some((shebang, rest)) if shebang.starts_with("#!") => {
+                format!("{}\n{}{}", shebang, block, rest)
+            }
+            _ => format!("#!/bin/sh\n{}{}", block, base),
+        },
+        _ => format!("#!/bin/sh\n{}", block),
+    };
+    fs::create_dir_all(&hooks_dir)?;
+    fs::write(&path, script)?;
+    let mut perms = fs::metadata(&path)?.permissions();
+    perms.set_mode(perms.mode() | 0o755);
+    fs::set_permissions(&path, perms)?;
+    info!("installed {} hook to {}", hook.name(), path.display());
+    Ok(())
+}
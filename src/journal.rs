@@ -0,0 +1,321 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! A journal of the files each `--fix` run changed, so that `clean undo` can
+//! restore them.
+//!
+//! Each run that fixes files gets a directory below `$XDG_STATE_HOME/clean/runs`
+//! named by its id, holding the original content of each fixed file and a
+//! `journal.jsonl` with a line per file, which is appended to before the
+//! file is replaced by its fixed content. A file that was recorded but never
+//! replaced still has its original content, which `undo` leaves alone. Only
+//! the last [`KEEP_RUNS`] runs are kept.
+//!
+//! The journal never keeps files from being fixed: files larger than
+//! [`STREAM_THRESHOLD`] are not saved, and once saving a file fails, the
+//! rest of the run is not recorded, with a warning.
+
+use anyhow::Result;
+use log::{info, warn};
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use xxhash_rust::xxh3::Xxh3;
+
+use clean::stream::STREAM_THRESHOLD;
+
+use crate::exit::Failure;
+use crate::rewrite;
+
+/// How many runs are kept to be undone.
+const KEEP_RUNS: usize = 10;
+
+/// A file fixed in a run.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Entry {
+    /// The absolute path of the file.
+    path: PathBuf,
+    /// The name of the file holding the original content in the run.
+    original: String,
+    original_hash: u64,
+    fixed_hash: u64,
+}
+
+/// The journal of the current run, created when the first file is fixed.
+#[derive(Debug, Default)]
+pub struct Journal {
+    run: Mutex<Option<Run>>,
+    next: AtomicUsize,
+    /// Set once the journal could not be written, the rest of the run is
+    /// not recorded.
+    failed: AtomicBool,
+}
+
+#[derive(Debug)]
+struct Run {
+    dir: PathBuf,
+    index: fs::File,
+}
+
+/// The original content of a file about to be fixed, saved in the journal.
+pub struct Saved {
+    path: PathBuf,
+    original: String,
+    original_hash: u64,
+}
+
+/// Returns `$XDG_STATE_HOME/clean/runs`, falling back to
+/// `~/.local/state/clean/runs`, or `None` if neither is known.
+fn runs_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(base.join("clean").join("runs"))
+}
+
+/// Returns the ids of all runs, oldest first.
+fn run_ids(dir: &Path) -> Vec<String> {
+    let mut ids: Vec<String> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok()?.file_name().into_string().ok())
+        .filter(|id| !id.starts_with('.'))
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// Hashes everything written through it.
+pub struct Hashing<W> {
+    inner: W,
+    hasher: Xxh3,
+}
+
+impl<W: Write> Hashing<W> {
+    pub fn new(inner: W) -> Self {
+        Hashing {
+            inner,
+            hasher: Xxh3::new(),
+        }
+    }
+
+    pub fn digest(&self) -> u64 {
+        self.hasher.digest()
+    }
+}
+
+impl<W: Write> Write for Hashing<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Returns the hash of the content of the file at `path`.
+fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut hashing = Hashing::new(io::sink());
+    io::copy(&mut fs::File::open(path)?, &mut hashing)?;
+    Ok(hashing.digest())
+}
+
+impl Journal {
+    /// Starts the run, pruning old runs.
+    fn start(&self) -> io::Result<Run> {
+        let Some(runs) = runs_dir() else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "neither XDG_STATE_HOME nor HOME is set",
+            ));
+        };
+        fs::create_dir_all(&runs)?;
+        let ids = run_ids(&runs);
+        for id in &ids[..ids.len().saturating_sub(KEEP_RUNS - 1)] {
+            let _ = fs::remove_dir_all(runs.join(id));
+        }
+        // Ids of a fixed width sort by the time the runs started.
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        let dir = runs.join(format!("{:016x}", nanos));
+        fs::create_dir(&dir)?;
+        let index = fs::File::create(dir.join("journal.jsonl"))?;
+        Ok(Run { dir, index })
+    }
+
+    /// Saves the original content of the file at `path` before it is fixed,
+    /// or returns `None` if it is not recorded.
+    pub fn save(&self, path: &Path) -> Option<Saved> {
+        if self.failed.load(Ordering::Relaxed) {
+            return None;
+        }
+        if fs::metadata(path).is_ok_and(|md| md.len() > STREAM_THRESHOLD) {
+            warn!(
+                "not saving '{}' for `clean undo`, it is larger than {} MiB",
+                path.display(),
+                STREAM_THRESHOLD >> 20
+            );
+            return None;
+        }
+        self.try_save(path).map_err(|e| self.fail(e)).ok()
+    }
+
+    /// Warns that the journal can not be written, once per run.
+    fn fail(&self, e: io::Error) {
+        if !self.failed.swap(true, Ordering::Relaxed) {
+            warn!(
+                "failed to save fixed files for `clean undo`, fixing them anyway: {}",
+                e
+            );
+        }
+    }
+
+    fn try_save(&self, path: &Path) -> io::Result<Saved> {
+        let dir = {
+            let mut run = self.run.lock().unwrap();
+            if run.is_none() {
+                *run = Some(self.start()?);
+            }
+            run.as_ref().unwrap().dir.clone()
+        };
+        let original = self.next.fetch_add(1, Ordering::Relaxed).to_string();
+        let mut out = Hashing::new(fs::File::create(dir.join(&original))?);
+        io::copy(&mut fs::File::open(path)?, &mut out)?;
+        out.inner.sync_all()?;
+        Ok(Saved {
+            path: fs::canonicalize(path)?,
+            original,
+            original_hash: out.digest(),
+        })
+    }
+
+    /// Records that the file saved as `saved` is about to be replaced by
+    /// fixed content with the hash `fixed_hash`.
+    pub fn record(&self, saved: Saved, fixed_hash: u64) {
+        if let Err(e) = self.try_record(saved, fixed_hash) {
+            self.fail(e);
+        }
+    }
+
+    fn try_record(&self, saved: Saved, fixed_hash: u64) -> io::Result<()> {
+        let entry = Entry {
+            path: saved.path,
+            original: saved.original,
+            original_hash: saved.original_hash,
+            fixed_hash,
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        let mut run = self.run.lock().unwrap();
+        let index = &mut run.as_mut().unwrap().index;
+        index.write_all(&line)?;
+        index.sync_data()
+    }
+
+    /// Returns the id of the run, if any file was fixed.
+    pub fn id(&self) -> Option<String> {
+        let run = self.run.lock().unwrap();
+        let dir = &run.as_ref()?.dir;
+        Some(dir.file_name()?.to_string_lossy().into_owned())
+    }
+}
+
+/// Prints the runs that can be undone, newest first, with the number of
+/// files each fixed.
+pub fn list(out: &mut dyn Write) -> Result<()> {
+    let Some(runs) = runs_dir() else {
+        return Ok(());
+    };
+    for id in run_ids(&runs).iter().rev() {
+        let entries = read_entries(&runs.join(id))?;
+        let plural = if entries.len() == 1 { "" } else { "s" };
+        writeln!(out, "{}  {} file{}", id, entries.len(), plural)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn read_entries(dir: &Path) -> Result<Vec<Entry>> {
+    let index = fs::read_to_string(dir.join("journal.jsonl"))?;
+    // A line cut short by a crash is left out.
+    Ok(index
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Restores the files fixed in the run `id`, by default the last one, and
+/// removes the run once all files are restored.
+///
+/// Files changed since the run are left alone unless `force` is set.
+pub fn undo(id: Option<&str>, force: bool) -> Result<()> {
+    let runs = runs_dir().unwrap_or_default();
+    let ids = run_ids(&runs);
+    let id = match id {
+        Some(id) if ids.iter().any(|i| i == id) => id,
+        Some(id) => {
+            let message = format!("unknown run '{}', see `clean undo --list`", id);
+            return Err(Failure::Usage(message).into());
+        }
+        None => match ids.last() {
+            Some(id) => id,
+            None => return Err(Failure::Usage("no fix run to undo".into()).into()),
+        },
+    };
+    let dir = runs.join(id);
+    let mut refused = 0;
+    for entry in read_entries(&dir)? {
+        let current = match hash_file(&entry.path) {
+            Ok(hash) => Some(hash),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        // Restored already, or the run ended before replacing the file.
+        if current == Some(entry.original_hash) {
+            continue;
+        }
+        if current != Some(entry.fixed_hash) && !force {
+            warn!(
+                "not restoring '{}', it was changed since run {}",
+                entry.path.display(),
+                id
+            );
+            refused += 1;
+            continue;
+        }
+        let original = dir.join(&entry.original);
+        if current.is_none() {
+            fs::copy(&original, &entry.path)?;
+        } else {
+            let in_place = fs::metadata(&entry.path)?.nlink() > 1;
+            let target = rewrite::Target {
+                path: entry.path.clone(),
+                in_place,
+            };
+            rewrite::replace(&target, |out| {
+                io::copy(&mut fs::File::open(&original)?, out).map(drop)
+            })?;
+        }
+        info!("restored '{}'", entry.path.display());
+    }
+    if refused > 0 {
+        let plural = if refused == 1 { "" } else { "s" };
+        let message = format!(
+            "{} file{} changed since run {}, use --force to restore anyway",
+            refused, plural, id
+        );
+        return Err(Failure::Fix(message).into());
+    }
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,207 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Linting text files for whitespace and line ending issues.
+//!
+//! This is the library behind the `clean` command. A [`Linter`] checks
+//! content against a [`WsRule`] and can fix what it finds:
+//!
+//! ```
+//! use clean::{IssueType, Linter};
+//!
+//! let linter = Linter::builder().ignore("*.md").build().unwrap();
+//! let issues = linter.lint_str("hello.txt", "hello \n").unwrap();
+//! assert_eq!(issues[0].issue_type, IssueType::TrailingWhitespace);
+//! assert_eq!(issues[0].line, Some(1));
+//! assert_eq!(linter.fix_str("a.txt", "hello \n"), "hello\n");
+//! assert!(linter.lint_str("README.md", "hello \n").unwrap().is_empty());
+//! ```
+//!
+//! The checks themselves are [`Rule`]s, run by a [`Registry`] that can be
+//! extended with custom rules, such as the [`RegexRule`]s and
+//! [`PluginRule`]s of a [`Config`].
+//! The lower level functions [`lint_file`] and [`fix_file`] run the built-in
+//! rules with an explicit [`WsRule`], and the [`whitespace`] module resolves
+//! it from git's `core.whitespace` and gitattributes.
+
+use std::path::PathBuf;
+
+mod builtin;
+pub mod config;
+mod error;
+pub mod git;
+mod linter;
+pub mod plugin;
+mod regex_rule;
+pub mod rule;
+pub mod stream;
+mod suppress;
+pub mod whitespace;
+
+pub use config::Config;
+pub use error::{Error, Result};
+pub use linter::{Linter, LinterBuilder};
+pub use plugin::PluginRule;
+pub use regex_rule::RegexRule;
+pub use rule::{Override, Registry, Rule, Severity};
+pub use whitespace::WsRule;
+
+/// The kind of an [`Issue`], serialized as the id of the rule that found it,
+/// such as `trailing_whitespace`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IssueType {
+    TrailingWhitespace,
+    MissingNewline,
+    CrlfLineEnding,
+    MultipleBlankLinesEof,
+    SpaceBeforeTab,
+    IndentWithNonTab,
+    TabInIndent,
+    /// A suppression directive that suppresses nothing.
+    UnusedSuppression,
+    /// An issue found by a rule that is not built in, by the id of the rule.
+    Custom(String),
+}
+
+/// A problem found in a file, as reported by `clean --json`.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq)]
+pub struct Issue {
+    #[serde(rename = "type")]
+    pub issue_type: IssueType,
+    /// 1-based line number.
+    pub line: Option<usize>,
+    pub file: String,
+    /// The severity of the rule in the file, as configured.
+    #[serde(default)]
+    pub severity: Severity,
+    /// A human readable description, left out of JSON and YAML reports
+    /// unless it differs from the message of the issue type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl IssueType {
+    const BUILTIN: &'static [IssueType] = &[
+        IssueType::TrailingWhitespace,
+        IssueType::MissingNewline,
+        IssueType::CrlfLineEnding,
+        IssueType::MultipleBlankLinesEof,
+        IssueType::SpaceBeforeTab,
+        IssueType::IndentWithNonTab,
+        IssueType::TabInIndent,
+        IssueType::UnusedSuppression,
+    ];
+
+    /// Returns the issue type of the rule `id`.
+    pub fn from_id(id: &str) -> Self {
+        IssueType::BUILTIN
+            .iter()
+            .find(|t| t.id() == id)
+            .cloned()
+            .unwrap_or_else(|| IssueType::Custom(id.to_string()))
+    }
+
+    /// The id of the rule that reports this issue type.
+    pub fn id(&self) -> &str {
+        match self {
+            IssueType::TrailingWhitespace => "trailing_whitespace",
+            IssueType::MissingNewline => "missing_newline",
+            IssueType::CrlfLineEnding => "crlf_line_ending",
+            IssueType::MultipleBlankLinesEof => "multiple_blank_lines_eof",
+            IssueType::SpaceBeforeTab => "space_before_tab",
+            IssueType::IndentWithNonTab => "indent_with_non_tab",
+            IssueType::TabInIndent => "tab_in_indent",
+            IssueType::UnusedSuppression => "unused_suppression",
+            IssueType::Custom(id) => id,
+        }
+    }
+
+    /// A human readable description of the issue type.
+    ///
+    /// Custom issue types only know their id, their issues carry the
+    /// message of the rule instead.
+    pub fn message(&self) -> &str {
+        match self {
+            IssueType::TrailingWhitespace => "Trailing whitespace",
+            IssueType::MissingNewline => "Missing newline at end of file",
+            IssueType::CrlfLineEnding => "Contains CRLF line endings",
+            IssueType::MultipleBlankLinesEof => "Multiple blank lines at end of file",
+            IssueType::SpaceBeforeTab => "Space before tab in indent",
+            IssueType::IndentWithNonTab => "Indent with spaces instead of tabs",
+            IssueType::TabInIndent => "Tab in indent",
+            IssueType::UnusedSuppression => "Unused suppression",
+            IssueType::Custom(id) => id,
+        }
+    }
+}
+
+impl serde::Serialize for IssueType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.id())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for IssueType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = String::deserialize(deserializer)?;
+        Ok(IssueType::from_id(&id))
+    }
+}
+
+impl Issue {
+    /// Creates an issue at `line` with the message of `issue_type` and the
+    /// default severity.
+    pub fn new(file: &str, issue_type: IssueType, line: usize) -> Self {
+        Issue {
+            message: Some(issue_type.message().into()),
+            issue_type,
+            line: Some(line),
+            file: file.to_string(),
+            severity: Severity::default(),
+        }
+    }
+}
+
+/// Returns whether `path` or its file name matches any of the glob patterns
+/// in `ignores`.
+pub fn should_ignore(path: &str, ignores: &[String]) -> Result<bool, glob::PatternError> {
+    for pat in ignores {
+        let pat_obj = glob::Pattern::new(pat)?;
+        if pat_obj.matches(path) {
+            return Ok(true);
+        }
+        if pat_obj.matches(
+            PathBuf::from(path)
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .as_ref(),
+        ) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Lints `content` with the built-in rules in a single pass over its bytes.
+///
+/// UTF-8 is not validated here, callers check it only when issues are found,
+/// so clean files are never decoded.
+pub fn lint_file(path: &str, content: &[u8], ws: &WsRule) -> Vec<Issue> {
+    Registry::builtin()
+        .lint(path, content, ws)
+        .expect("built-in rules do not fail")
+}
+
+/// Returns `content` with all issues of the built-in rules enabled in `ws`
+/// fixed.
+pub fn fix_file(content: &str, ws: &WsRule) -> String {
+    Registry::builtin().fix("", content, ws)
+}
+
+/// Fixes the issues of the built-in rules in a single line, without its
+/// line terminator.
+pub fn fix_line(line: &str, ws: &WsRule) -> String {
+    Registry::builtin().fix_line("", line, ws)
+}
@@ -0,0 +1,137 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! A configured linter, the main entry point of the library.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::stream::{self, STREAM_THRESHOLD};
+use crate::whitespace::{RuleResolvers, WsRule};
+use crate::{should_ignore, Error, Issue, Registry, Result};
+
+/// Builds a [`Linter`].
+#[derive(Debug, Clone, Default)]
+pub struct LinterBuilder {
+    rule: WsRule,
+    registry: Registry,
+    ignores: Vec<String>,
+    git: bool,
+}
+
+impl LinterBuilder {
+    /// Sets the whitespace rule, [`WsRule::default`] if not set.
+    pub fn rule(mut self, rule: WsRule) -> Self {
+        self.rule = rule;
+        self
+    }
+
+    /// Sets the rules that are checked, the built-in ones if not set.
+    pub fn registry(mut self, registry: Registry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Adds a glob pattern of paths that are not linted, matched against the
+    /// whole path and the file name.
+    pub fn ignore(mut self, pattern: impl Into<String>) -> Self {
+        self.ignores.push(pattern.into());
+        self
+    }
+
+    /// Follows `core.whitespace` and gitattributes of files linted with
+    /// [`Linter::lint_path`] instead of the rule set with
+    /// [`rule`](Self::rule).
+    pub fn git(mut self, git: bool) -> Self {
+        self.git = git;
+        self
+    }
+
+    /// Builds the linter, failing if an ignore pattern is not a valid glob.
+    pub fn build(self) -> Result<Linter> {
+        for pattern in &self.ignores {
+            glob::Pattern::new(pattern)?;
+        }
+        Ok(Linter {
+            rule: self.rule,
+            registry: self.registry,
+            ignores: self.ignores,
+            resolvers: self.git.then(|| Mutex::new(RuleResolvers::default())),
+        })
+    }
+}
+
+/// Lints and fixes text with a fixed configuration.
+///
+/// A linter can be shared between threads.
+#[derive(Default)]
+pub struct Linter {
+    rule: WsRule,
+    registry: Registry,
+    ignores: Vec<String>,
+    resolvers: Option<Mutex<RuleResolvers>>,
+}
+
+impl Linter {
+    /// Returns a builder for a linter.
+    pub fn builder() -> LinterBuilder {
+        LinterBuilder::default()
+    }
+
+    fn ignored(&self, path: &str) -> bool {
+        // Patterns were validated by the builder.
+        should_ignore(path, &self.ignores).unwrap_or(false)
+    }
+
+    /// Lints `content`, reporting issues against `path`.
+    ///
+    /// Nothing is reported if `path` is ignored. Fails only if a rule
+    /// fails, such as a plugin.
+    pub fn lint_str(&self, path: &str, content: &str) -> Result<Vec<Issue>> {
+        if self.ignored(path) {
+            return Ok(Vec::new());
+        }
+        self.registry.lint(path, content.as_bytes(), &self.rule)
+    }
+
+    /// Reads and lints the file at `path`.
+    ///
+    /// Nothing is reported if `path` is ignored, or skipped by its
+    /// gitattributes. Large files are streamed with bounded memory. Fails
+    /// with [`Error::NotUtf8`] if the file has issues but is not valid UTF-8.
+    pub fn lint_path(&self, path: impl AsRef<Path>) -> Result<Vec<Issue>> {
+        let path = path.as_ref();
+        let path_str = path.to_string_lossy();
+        if self.ignored(&path_str) {
+            return Ok(Vec::new());
+        }
+        let rule = match self.resolvers {
+            Some(ref resolvers) => match resolvers.lock().unwrap().rule_for(path)? {
+                Some(rule) => rule,
+                None => return Ok(Vec::new()),
+            },
+            None => self.rule,
+        };
+        if fs::metadata(path)?.len() > STREAM_THRESHOLD {
+            return stream::lint_reader(&self.registry, &path_str, fs::File::open(path)?, &rule);
+        }
+        let content = fs::read(path)?;
+        let issues = self.registry.lint(&path_str, &content, &rule)?;
+        if !issues.is_empty() && std::str::from_utf8(&content).is_err() {
+            return Err(Error::NotUtf8(path.to_path_buf()));
+        }
+        Ok(issues)
+    }
+
+    /// Returns `content` of `path` with all issues fixed.
+    ///
+    /// The content is returned as is if `path` is ignored.
+    pub fn fix_str(&self, path: &str, content: &str) -> String {
+        if self.ignored(path) {
+            return content.to_string();
+        }
+        self.registry.fix(path, content, &self.rule)
+    }
+}
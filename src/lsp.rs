@@ -0,0 +1,727 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! A language server publishing issues as diagnostics, see `clean lsp`.
+//!
+//! Ignore patterns come from `--ignore` and the `ignore` setting, given as
+//! initialization options or with `workspace/didChangeConfiguration`, either
+//! at the top level or below a `clean` key. Whitespace rules follow
+//! `core.whitespace` and gitattributes like the command line does.
+//!
+//! The configuration is looked up from the root of the workspace, and
+//! documents are linted by their path relative to it. Documents are linted
+//! again once edits pause for [`DEBOUNCE`], so that plugins do not run on
+//! every keystroke.
+
+use anyhow::Result;
+use log::warn;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeConfiguration, DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{CodeActionRequest, Formatting, Request as _};
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, Diagnostic, DiagnosticSeverity, DocumentFormattingParams,
+    InitializeParams, NumberOrString, OneOf, Position, PublishDiagnosticsParams, Range,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url,
+    WorkspaceEdit,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use clean::whitespace::{RuleResolvers, WsRule};
+use clean::{should_ignore, Issue, IssueType, Registry, Severity};
+
+/// How long edits of a document must pause before it is linted again.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Runs the language server over stdin and stdout until the client exits.
+///
+/// `load` returns the registry for the root of the workspace, if the client
+/// gives one.
+pub fn run(load: impl FnOnce(Option<&Path>) -> Result<Registry>, ignores: &[String]) -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(connection, load, ignores.to_vec())?;
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Runs the language server on `connection`, until the client shuts it down.
+pub fn serve(
+    connection: Connection,
+    load: impl FnOnce(Option<&Path>) -> Result<Registry>,
+    ignores: Vec<String>,
+) -> Result<()> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    let params = connection.initialize(serde_json::to_value(capabilities)?)?;
+    let params: InitializeParams = serde_json::from_value(params)?;
+    let mut server = Server::new(connection, ignores, params);
+    server.registry = load(server.root.as_deref())?;
+    if let Some(root) = &server.root {
+        // Documents are linted by their path relative to the root.
+        server.registry.relative_to(root);
+    }
+    server.main_loop()
+}
+
+/// Reads the `ignore` setting from client settings.
+fn setting_ignores(settings: &serde_json::Value) -> Vec<String> {
+    let settings = settings.get("clean").unwrap_or(settings);
+    settings
+        .get("ignore")
+        .and_then(|v| v.as_array())
+        .map(|patterns| {
+            patterns
+                .iter()
+                .filter_map(|p| p.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn utf16_len(s: &str) -> u32 {
+    s.encode_utf16().count() as u32
+}
+
+/// The range of the whole document.
+fn full_range(lines: &[&str]) -> Range {
+    let last = lines.len() - 1;
+    Range::new(
+        Position::new(0, 0),
+        Position::new(last as u32, utf16_len(lines[last])),
+    )
+}
+
+/// The range of the text an issue is about.
+fn issue_range(lines: &[&str], issue: &Issue) -> Range {
+    let index = issue.line.unwrap_or(1).clamp(1, lines.len()) - 1;
+    let line = lines[index];
+    let at = |start, end| {
+        Range::new(
+            Position::new(index as u32, start),
+            Position::new(index as u32, end),
+        )
+    };
+    match issue.issue_type {
+        IssueType::TrailingWhitespace => at(utf16_len(line.trim_end()), utf16_len(line)),
+        IssueType::SpaceBeforeTab | IssueType::IndentWithNonTab | IssueType::TabInIndent => {
+            let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+            at(0, indent as u32)
+        }
+        IssueType::CrlfLineEnding => {
+            let content = line.strip_suffix('\r').unwrap_or(line);
+            at(utf16_len(content), utf16_len(line))
+        }
+        IssueType::MissingNewline => at(utf16_len(line), utf16_len(line)),
+        IssueType::UnusedSuppression | IssueType::Custom(_) => at(0, utf16_len(line)),
+        IssueType::MultipleBlankLinesEof => {
+            // From the first of the blank lines to the end of the file.
+            let first = lines[..index]
+                .iter()
+                .rposition(|l| !l.trim_end().is_empty())
+                .map_or(0, |i| i + 1);
+            Range::new(
+                Position::new(first as u32, 0),
+                Position::new(index as u32, utf16_len(line)),
+            )
+        }
+    }
+}
+
+/// Whether an issue can be fixed by rewriting its line alone.
+fn is_line_issue(issue_type: &IssueType) -> bool {
+    !matches!(
+        issue_type,
+        IssueType::MissingNewline
+            | IssueType::MultipleBlankLinesEof
+            | IssueType::UnusedSuppression
+            | IssueType::Custom(_)
+    )
+}
+
+fn to_diagnostic(lines: &[&str], issue: &Issue) -> Diagnostic {
+    let code = serde_json::to_value(&issue.issue_type)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string));
+    Diagnostic {
+        range: issue_range(lines, issue),
+        severity: Some(match issue.severity {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+            Severity::Info => DiagnosticSeverity::INFORMATION,
+        }),
+        code: code.map(NumberOrString::String),
+        source: Some("clean".into()),
+        message: issue.message.clone().unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+struct Server {
+    connection: Connection,
+    registry: Registry,
+    cli_ignores: Vec<String>,
+    setting_ignores: Vec<String>,
+    root: Option<PathBuf>,
+    resolvers: RuleResolvers,
+    documents: HashMap<Url, String>,
+    /// Documents changed since they were last linted, with the time of the
+    /// last change.
+    changed: HashMap<Url, Instant>,
+}
+
+impl Server {
+    fn new(connection: Connection, ignores: Vec<String>, params: InitializeParams) -> Self {
+        #[allow(deprecated)]
+        let root_uri = params
+            .workspace_folders
+            .as_ref()
+            .and_then(|folders| folders.first())
+            .map(|f| f.uri.clone())
+            .or(params.root_uri);
+        Server {
+            connection,
+            registry: Registry::default(),
+            cli_ignores: ignores,
+            setting_ignores: params
+                .initialization_options
+                .as_ref()
+                .map(setting_ignores)
+                .unwrap_or_default(),
+            root: root_uri.and_then(|uri| uri.to_file_path().ok()),
+            resolvers: RuleResolvers::default(),
+            documents: HashMap::new(),
+            changed: HashMap::new(),
+        }
+    }
+
+    fn main_loop(&mut self) -> Result<()> {
+        let receiver = self.connection.receiver.clone();
+        loop {
+            let message = match self.changed.values().min() {
+                Some(&last) => {
+                    let timeout = (last + DEBOUNCE).saturating_duration_since(Instant::now());
+                    match receiver.recv_timeout(timeout) {
+                        Ok(message) => Some(message),
+                        Err(e) if e.is_timeout() => None,
+                        Err(_) => return Ok(()),
+                    }
+                }
+                None => match receiver.recv() {
+                    Ok(message) => Some(message),
+                    Err(_) => return Ok(()),
+                },
+            };
+            self.publish_changed()?;
+            let Some(message) = message else {
+                continue;
+            };
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    let response = self.handle_request(request);
+                    self.connection.sender.send(response.into())?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+    }
+
+    /// Publishes the diagnostics of the documents whose edits paused.
+    fn publish_changed(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let due: Vec<Url> = self
+            .changed
+            .iter()
+            .filter(|(_, &last)| now.duration_since(last) >= DEBOUNCE)
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        for uri in due {
+            self.changed.remove(&uri);
+            self.publish(uri)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the path of a document relative to the root of the
+    /// workspace, or its URI if it is not a file.
+    fn path_of(&self, uri: &Url) -> String {
+        let Ok(path) = uri.to_file_path() else {
+            return uri.to_string();
+        };
+        let rel = self
+            .root
+            .as_ref()
+            .and_then(|root| path.strip_prefix(root).ok())
+            .unwrap_or(&path);
+        rel.to_string_lossy().into_owned()
+    }
+
+    /// Returns the rule for a document, or `None` if it is ignored.
+    fn rule_for(&mut self, uri: &Url) -> Option<WsRule> {
+        // Documents that are not files, such as unsaved ones, get the defaults.
+        let Ok(path) = uri.to_file_path() else {
+            return Some(WsRule::default());
+        };
+        let ignores = [&self.cli_ignores[..], &self.setting_ignores[..]].concat();
+        match should_ignore(&self.path_of(uri), &ignores) {
+            Ok(true) => return None,
+            Ok(false) => {}
+            Err(e) => warn!("Invalid glob pattern: {}", e),
+        }
+        match self.resolvers.rule_for(&path) {
+            Ok(rule) => rule,
+            Err(e) => {
+                warn!(
+                    "failed to resolve whitespace rule for '{}': {}",
+                    path.display(),
+                    e
+                );
+                Some(WsRule::default())
+            }
+        }
+    }
+
+    /// Lints a document, returning its text, rule and issues.
+    fn lint(&mut self, uri: &Url) -> Option<(String, WsRule, Vec<Issue>)> {
+        let text = self.documents.get(uri)?.clone();
+        let ws = self.rule_for(uri)?;
+        let issues = match self.registry.lint(&self.path_of(uri), text.as_bytes(), &ws) {
+            Ok(issues) => issues,
+            Err(e) => {
+                warn!("failed to lint '{}': {}", uri, e);
+                return None;
+            }
+        };
+        Some((text, ws, issues))
+    }
+
+    fn publish(&mut self, uri: Url) -> Result<()> {
+        let diagnostics = match self.lint(&uri) {
+            Some((text, _, issues)) => {
+                let lines: Vec<&str> = text.split('\n').collect();
+                issues.iter().map(|i| to_diagnostic(&lines, i)).collect()
+            }
+            None => Vec::new(),
+        };
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        };
+        let notification = Notification::new(PublishDiagnostics::METHOD.into(), params);
+        self.connection.sender.send(notification.into())?;
+        Ok(())
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let uri = params.text_document.uri;
+                self.documents
+                    .insert(uri.clone(), params.text_document.text);
+                self.publish(uri)?;
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let uri = params.text_document.uri;
+                // With full sync, the last change holds the whole text.
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents.insert(uri.clone(), change.text);
+                }
+                self.changed.insert(uri, Instant::now());
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                self.changed.remove(&uri);
+                self.publish(uri)?;
+            }
+            DidChangeConfiguration::METHOD => {
+                let params: lsp_types::DidChangeConfigurationParams =
+                    serde_json::from_value(notification.params)?;
+                self.setting_ignores = setting_ignores(&params.settings);
+                let uris: Vec<Url> = self.documents.keys().cloned().collect();
+                for uri in uris {
+                    self.publish(uri)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_request(&mut self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            CodeActionRequest::METHOD => serde_json::from_value(request.params)
+                .map(|params| serde_json::to_value(self.code_actions(params))),
+            Formatting::METHOD => serde_json::from_value(request.params)
+                .map(|params| serde_json::to_value(self.format(params))),
+            _ => {
+                return Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("unsupported request '{}'", request.method),
+                )
+            }
+        };
+        match result {
+            Ok(Ok(value)) => Response {
+                id,
+                result: Some(value),
+                error: None,
+            },
+            Ok(Err(e)) | Err(e) => {
+                Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string())
+            }
+        }
+    }
+
+    /// Offers to fix the lines with issues in the requested range, and the
+    /// whole document.
+    fn code_actions(&mut self, params: CodeActionParams) -> Vec<CodeActionOrCommand> {
+        let uri = params.text_document.uri;
+        let Some((text, ws, issues)) = self.lint(&uri) else {
+            return Vec::new();
+        };
+        let path = self.path_of(&uri);
+        let lines: Vec<&str> = text.split('\n').collect();
+        let edit = |edits: Vec<TextEdit>| WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..Default::default()
+        };
+        let mut actions = Vec::new();
+        let first = params.range.start.line as usize + 1;
+        let last = params.range.end.line as usize + 1;
+        let mut fixed_lines = Vec::new();
+        for issue in &issues {
+            let Some(n) = issue.line.filter(|n| (first..=last).contains(n)) else {
+                continue;
+            };
+            if !is_line_issue(&issue.issue_type) || fixed_lines.contains(&n) {
+                continue;
+            }
+            fixed_lines.push(n);
+            let line = lines[n - 1];
+            // The CR is dropped along with the rest of a CRLF line ending.
+            let fixed = match line.strip_suffix('\r') {
+                Some(content) if ws.crlf => self.registry.fix_line(&path, content, &ws),
+                _ => self.registry.fix_line(&path, line, &ws),
+            };
+            let diagnostics = issues
+                .iter()
+                .filter(|i| i.line == Some(n) && is_line_issue(&i.issue_type))
+                .map(|i| to_diagnostic(&lines, i))
+                .collect();
+            let range = Range::new(
+                Position::new(n as u32 - 1, 0),
+                Position::new(n as u32 - 1, utf16_len(line)),
+            );
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Fix whitespace on line {}", n),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(diagnostics),
+                edit: Some(edit(vec![TextEdit::new(range, fixed)])),
+                ..Default::default()
+            }));
+        }
+        if !issues.is_empty() {
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: "Fix all whitespace issues in the file".into(),
+                kind: Some(CodeActionKind::SOURCE_FIX_ALL),
+                diagnostics: Some(issues.iter().map(|i| to_diagnostic(&lines, i)).collect()),
+                edit: Some(edit(vec![TextEdit::new(
+                    full_range(&lines),
+                    self.registry.fix(&path, &text, &ws),
+                )])),
+                ..Default::default()
+            }));
+        }
+        actions
+    }
+
+    fn format(&mut self, params: DocumentFormattingParams) -> Vec<TextEdit> {
+        let Some((text, ws, _)) = self.lint(&params.text_document.uri) else {
+            return Vec::new();
+        };
+        let path = self.path_of(&params.text_document.uri);
+        let fixed = self.registry.fix(&path, &text, &ws);
+        if fixed == text {
+            return Vec::new();
+        }
+        let lines: Vec<&str> = text.split('\n').collect();
+        vec![TextEdit::new(full_range(&lines), fixed)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clean::Config;
+    use lsp_server::RequestId;
+    use lsp_types::{
+        DidChangeTextDocumentParams, DidOpenTextDocumentParams, TextDocumentContentChangeEvent,
+        TextDocumentIdentifier, TextDocumentItem, VersionedTextDocumentIdentifier,
+    };
+    use std::thread::JoinHandle;
+
+    /// An in-process client talking to a server running on another thread.
+    struct Client {
+        connection: Connection,
+        server: JoinHandle<Result<()>>,
+        next_id: i32,
+    }
+
+    impl Client {
+        fn start(initialization_options: serde_json::Value) -> Self {
+            Client::start_in(None, initialization_options)
+        }
+
+        /// Starts a server for the workspace at `root`, with the
+        /// configuration found from it.
+        fn start_in(root: Option<&Path>, initialization_options: serde_json::Value) -> Self {
+            let (server, connection) = Connection::memory();
+            let load = |root: Option<&Path>| {
+                let config = match root.and_then(Config::find) {
+                    Some(path) => Config::load(&path)?,
+                    None => Config::default(),
+                };
+                Ok(config.registry())
+            };
+            let server = std::thread::spawn(move || serve(server, load, Vec::new()));
+            let mut client = Client {
+                connection,
+                server,
+                next_id: 0,
+            };
+            let params = serde_json::json!({
+                "capabilities": {},
+                "initializationOptions": initialization_options,
+                "rootUri": root.map(|root| Url::from_directory_path(root).unwrap()),
+            });
+            client.request_raw("initialize", params);
+            client.notify("initialized", serde_json::json!({}));
+            client
+        }
+
+        fn request_raw(&mut self, method: &str, params: serde_json::Value) -> serde_json::Value {
+            self.next_id += 1;
+            let id = RequestId::from(self.next_id);
+            let request = Request::new(id.clone(), method.into(), params);
+            self.connection.sender.send(request.into()).unwrap();
+            for message in &self.connection.receiver {
+                if let Message::Response(response) = message {
+                    assert_eq!(response.id, id);
+                    assert!(response.error.is_none(), "{:?}", response.error);
+                    return response.result.unwrap_or_default();
+                }
+            }
+            panic!("server exited before responding to {}", method);
+        }
+
+        fn request<R: lsp_types::request::Request>(&mut self, params: R::Params) -> R::Result {
+            let result = self.request_raw(R::METHOD, serde_json::to_value(params).unwrap());
+            serde_json::from_value(result).unwrap()
+        }
+
+        fn notify(&self, method: &str, params: impl serde::Serialize) {
+            let notification = Notification::new(method.into(), params);
+            self.connection.sender.send(notification.into()).unwrap();
+        }
+
+        fn diagnostics(&self) -> Vec<Diagnostic> {
+            for message in &self.connection.receiver {
+                if let Message::Notification(n) = message {
+                    if n.method == PublishDiagnostics::METHOD {
+                        let params: PublishDiagnosticsParams =
+                            serde_json::from_value(n.params).unwrap();
+                        return params.diagnostics;
+                    }
+                }
+            }
+            panic!("server exited before publishing diagnostics");
+        }
+
+        fn open(&self, uri: &Url, text: &str) -> Vec<Diagnostic> {
+            let item = TextDocumentItem::new(uri.clone(), "plaintext".into(), 1, text.into());
+            self.notify(
+                DidOpenTextDocument::METHOD,
+                DidOpenTextDocumentParams {
+                    text_document: item,
+                },
+            );
+            self.diagnostics()
+        }
+
+        fn shutdown(mut self) {
+            self.request_raw("shutdown", serde_json::Value::Null);
+            self.notify("exit", serde_json::Value::Null);
+            self.server.join().unwrap().unwrap();
+        }
+    }
+
+    fn uri(name: &str) -> Url {
+        Url::parse(&format!("file:///clean-lsp-test/{}", name)).unwrap()
+    }
+
+    fn apply(text: &str, edit: &TextEdit) -> String {
+        // The tests only use ASCII text, where UTF-16 offsets are byte offsets.
+        let offset = |p: Position| {
+            let lines: Vec<&str> = text.split('\n').collect();
+            lines[..p.line as usize]
+                .iter()
+                .map(|l| l.len() + 1)
+                .sum::<usize>()
+                + p.character as usize
+        };
+        let (start, end) = (offset(edit.range.start), offset(edit.range.end));
+        format!("{}{}{}", &text[..start], edit.new_text, &text[end..])
+    }
+
+    #[test]
+    fn publishes_diagnostics_on_open_and_change() {
+        let client = Client::start(serde_json::Value::Null);
+        let uri = uri("a.txt");
+        let diagnostics = client.open(&uri, "ok\nbad  \n\tgood");
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.code.clone(), d.range))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    Some(NumberOrString::String("trailing_whitespace".into())),
+                    Range::new(Position::new(1, 3), Position::new(1, 5)),
+                ),
+                (
+                    Some(NumberOrString::String("missing_newline".into())),
+                    Range::new(Position::new(2, 5), Position::new(2, 5)),
+                ),
+            ]
+        );
+
+        client.notify(
+            DidChangeTextDocument::METHOD,
+            DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier::new(uri, 2),
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: "ok\n".into(),
+                }],
+            },
+        );
+        assert!(client.diagnostics().is_empty());
+        client.shutdown();
+    }
+
+    #[test]
+    fn code_actions_fix_line_and_file() {
+        let mut client = Client::start(serde_json::Value::Null);
+        let uri = uri("a.txt");
+        let text = "a \nb\r\nc \n\n\n";
+        client.open(&uri, text);
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: Range::new(Position::new(0, 0), Position::new(1, 0)),
+            context: Default::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = client.request::<CodeActionRequest>(params).unwrap();
+        let edits: Vec<(String, TextEdit)> = actions
+            .into_iter()
+            .map(|a| match a {
+                CodeActionOrCommand::CodeAction(a) => {
+                    let mut changes = a.edit.unwrap().changes.unwrap();
+                    (a.title, changes.remove(&uri).unwrap().remove(0))
+                }
+                CodeActionOrCommand::Command(_) => panic!("unexpected command"),
+            })
+            .collect();
+        let titles: Vec<&str> = edits.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(
+            titles,
+            [
+                "Fix whitespace on line 1",
+                "Fix whitespace on line 2",
+                "Fix all whitespace issues in the file"
+            ]
+        );
+        assert_eq!(apply(text, &edits[0].1), "a\nb\r\nc \n\n\n");
+        assert_eq!(apply(text, &edits[1].1), "a \nb\nc \n\n\n");
+        assert_eq!(apply(text, &edits[2].1), "a\nb\nc\n");
+        client.shutdown();
+    }
+
+    #[test]
+    fn formatting_fixes_the_document() {
+        let mut client = Client::start(serde_json::Value::Null);
+        let uri = uri("a.txt");
+        let text = "a  \nb";
+        client.open(&uri, text);
+        let params = DocumentFormattingParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            options: Default::default(),
+            work_done_progress_params: Default::default(),
+        };
+        let edits = client.request::<Formatting>(params.clone()).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(apply(text, &edits[0]), "a\nb\n");
+
+        client.open(&uri, "a\nb\n");
+        assert_eq!(client.request::<Formatting>(params).unwrap(), Vec::new());
+        client.shutdown();
+    }
+
+    #[test]
+    fn ignore_setting_is_respected() {
+        let client = Client::start(serde_json::json!({ "ignore": ["*.md"] }));
+        assert!(client.open(&uri("README.md"), "a \n").is_empty());
+        assert_eq!(client.open(&uri("a.txt"), "a \n").len(), 1);
+
+        let settings = serde_json::json!({ "settings": { "clean": { "ignore": ["*.txt"] } } });
+        client.notify(DidChangeConfiguration::METHOD, settings);
+        // Open documents are published again, in any order.
+        let mut counts = vec![client.diagnostics().len(), client.diagnostics().len()];
+        counts.sort();
+        assert_eq!(counts, [0, 1]);
+        client.shutdown();
+    }
+
+    #[test]
+    fn configuration_of_the_workspace_is_used() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().canonicalize().unwrap();
+        std::fs::write(
+            root.join(".clean.toml"),
+            "[[overrides]]\nfiles = [\"docs/**\"]\nrules.trailing_whitespace = \"off\"\n",
+        )
+        .unwrap();
+        let client = Client::start_in(Some(&root), serde_json::Value::Null);
+        let docs = Url::from_file_path(root.join("docs/a.md")).unwrap();
+        assert!(client.open(&docs, "a \n").is_empty());
+        let other = Url::from_file_path(root.join("a.md")).unwrap();
+        assert_eq!(client.open(&other, "a \n").len(), 1);
+        client.shutdown();
+    }
+}
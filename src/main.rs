@@ -3,15 +3,37 @@
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
 use anyhow::Result;
-use clap::{ArgAction, Parser};
-use log::{error, warn};
+use clap::{ArgAction, ArgGroup, Parser, Subcommand, ValueEnum};
+use ignore::{WalkBuilder, WalkState};
+use log::{error, info, warn};
 use std::collections::HashSet;
 use std::fs;
-use std::io::{self, Write};
-use std::os::unix::process::ExitStatusExt;
+use std::io::{self, Read, Write};
+use std::num::NonZeroUsize;
+use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
-use std::process::Command;
-use walkdir::WalkDir;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+mod cache;
+mod catalog;
+mod commit_msg;
+mod diff;
+mod exit;
+mod filter;
+mod hooks;
+mod journal;
+mod lsp;
+mod rewrite;
+mod summary;
+mod watch;
+
+use cache::Cache;
+use exit::Failure;
+use summary::FixedFile;
+use clean::whitespace::{RuleResolver, RuleResolvers, WsRule};
+use clean::{git, should_ignore, stream, Config, Issue, Override, Registry, Severity};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -25,7 +47,10 @@ end of file, CRLF line endings, and multiple blank lines at EOF.
 See the full documentation and usage examples at:
 https://github.com/black-desk/clean#readme"
 )]
+#[command(group(ArgGroup::new("fixing").args(["fix", "check"])))]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
     /// Output results in JSON format
     #[arg(long, action = ArgAction::SetTrue)]
     json: bool,
@@ -33,14 +58,20 @@ struct Cli {
     #[arg(long, action = ArgAction::SetTrue)]
     yaml: bool,
     /// Ignore file or path (supports glob, can be set multiple times)
-    #[arg(long, value_name = "PATTERN", num_args = 0.., action = ArgAction::Append, env = "CLEAN_IGNORE", value_delimiter = ':')]
+    #[arg(long, value_name = "PATTERN", num_args = 0.., action = ArgAction::Append, env = "CLEAN_IGNORE", value_delimiter = ':', global = true)]
     ignore: Vec<String>,
     /// Write output to file instead of stdout
     #[arg(short, long, value_name = "FILE")]
     output: Option<PathBuf>,
-    /// Directories to lint (default: current directory)
-    #[arg(value_name = "DIR", default_value = ".", num_args = 0..)]
-    dirs: Vec<PathBuf>,
+    /// Files or directories to lint (default: current directory)
+    #[arg(value_name = "PATH", num_args = 0..)]
+    paths: Vec<PathBuf>,
+    /// Read paths to lint from FILE, one per line ("-" for stdin)
+    #[arg(long, value_name = "FILE")]
+    files_from: Option<PathBuf>,
+    /// Paths read by --files-from are separated by NUL instead of newline
+    #[arg(short = 'z', long, action = ArgAction::SetTrue, requires = "files_from")]
+    null: bool,
     /// Only lint files tracked by git (auto-enabled in git repo)
     ///
     /// If not set, tracked files are linted only if the directory is a git repository.
@@ -51,149 +82,844 @@ struct Cli {
     /// Automatically fix all detected issues
     #[arg(long, action = ArgAction::SetTrue)]
     fix: bool,
+    /// Check whether --fix would change any file, without writing it
+    ///
+    /// Issues are reported as usual, but only fixable ones fail.
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with = "fix")]
+    check: bool,
+    /// With --fix or --check, print the fixes as a unified diff instead of writing them
+    ///
+    /// Fails if any file would be changed. Use --output to save the diff as a
+    /// patch that `git apply` accepts.
+    #[arg(long, action = ArgAction::SetTrue, requires = "fixing", conflicts_with_all = ["json", "yaml"])]
+    diff: bool,
+    /// With --fix, keep a copy of each fixed file named with SUFFIX appended
+    #[arg(
+        long,
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "~",
+        requires = "fix"
+    )]
+    backup: Option<String>,
+    /// With --fix, do not save the original files for `clean undo`
+    #[arg(long, action = ArgAction::SetTrue, requires = "fix")]
+    no_journal: bool,
+    /// How --fix treats symbolic links
+    #[arg(long, value_enum, value_name = "POLICY", default_value = "skip")]
+    symlinks: rewrite::Symlinks,
+    /// How --fix treats files with more than one hard link
+    #[arg(long, value_enum, value_name = "POLICY", default_value = "skip")]
+    hard_links: rewrite::HardLinks,
+    /// The canonical linted directories and the current directory, which
+    /// symbolic links may point into to be fixed.
+    #[arg(skip)]
+    roots: Vec<PathBuf>,
+    /// The journal of the files fixed in this run.
+    #[arg(skip)]
+    journal: journal::Journal,
+    /// Lint content read from stdin instead of files
+    ///
+    /// With --fix, the fixed content is written to stdout instead of a report.
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["paths", "files_from"])]
+    stdin: bool,
+    /// File name used for stdin content in reports and ignore rules
+    #[arg(long, value_name = "PATH", requires = "stdin")]
+    stdin_filename: Option<String>,
+    /// Lint the staged content of files in the index, for use in a pre-commit hook
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["paths", "files_from", "stdin", "fix", "check"])]
+    staged: bool,
+    /// Number of files to lint in parallel (default: number of CPUs)
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+    /// Keep running and lint files again when they change
+    ///
+    /// The report is printed again after every change, on a cleared screen if
+    /// the output is a terminal. With --fix, files are fixed as they are saved.
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["stdin", "staged", "check", "diff"])]
+    watch: bool,
+    /// Do not read or write cached results of unchanged files
+    #[arg(long, action = ArgAction::SetTrue, global = true)]
+    no_cache: bool,
+    /// Read custom rules from FILE instead of the closest .clean.toml
+    #[arg(long, value_name = "FILE", env = "CLEAN_CONFIG", global = true)]
+    config: Option<PathBuf>,
+    /// Fail only if issues of at least this severity are found
+    ///
+    /// Issues of rules with the `info` severity are reported, but never fail.
+    #[arg(long, value_enum, value_name = "SEVERITY", default_value = "warning", global = true)]
+    fail_on: FailOn,
+    /// Only check and fix these rules (comma separated, can be set multiple times)
+    #[arg(long, value_name = "RULE", value_delimiter = ',', action = ArgAction::Append, global = true)]
+    select: Vec<String>,
+    /// Do not check and fix these rules (comma separated, can be set multiple times)
+    #[arg(long, value_name = "RULE", value_delimiter = ',', action = ArgAction::Append, global = true)]
+    skip: Vec<String>,
 }
 
-#[derive(Debug, serde::Serialize, Clone)]
-#[serde(rename_all = "snake_case")]
-enum IssueType {
-    TrailingWhitespace,
-    MissingNewline,
-    CrlfLineEnding,
-    MultipleBlankLinesEof,
+impl Cli {
+    /// Whether fixes are only shown, not written.
+    fn dry_run(&self) -> bool {
+        self.check || self.diff
+    }
+
+    fn rewrite_policy(&self) -> rewrite::Policy<'_> {
+        rewrite::Policy {
+            symlinks: self.symlinks,
+            hard_links: self.hard_links,
+            roots: &self.roots,
+        }
+    }
 }
 
-#[derive(Debug, serde::Serialize, Clone)]
-struct Issue {
-    #[serde(rename = "type")]
-    issue_type: IssueType,
-    line: Option<usize>,
-    file: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    message: Option<String>,
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FailOn {
+    Warning,
+    Error,
 }
 
-fn is_git_repo(dir: &std::path::Path) -> bool {
-    dir.join(".git").exists()
+impl FailOn {
+    fn severity(self) -> Severity {
+        match self {
+            FailOn::Warning => Severity::Warning,
+            FailOn::Error => Severity::Error,
+        }
+    }
 }
 
-fn git_tracked_files(dir: &std::path::Path) -> anyhow::Result<HashSet<String>> {
-    let output = Command::new("git")
-        .arg("ls-files")
-        .current_dir(dir)
-        .output()?;
-    if !output.status.success() {
-        match output.status.code() {
-            Some(code) => {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                anyhow::bail!("`git ls-files` exit with code={}: {}", code, stderr.trim());
-            }
-            _ => {
-                anyhow::bail!(
-                    "`git ls-files` killed by signal: {}",
-                    output.status.signal().unwrap()
-                );
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Run as a git long-running filter process
+    ///
+    /// Normalizes whitespace of staged content. Configure it with
+    /// `git config filter.clean.process "clean filter-process"` and
+    /// `* filter=clean` in .gitattributes.
+    FilterProcess,
+    /// Lint a commit message file, for use in a commit-msg hook
+    ///
+    /// Comment lines (starting with `core.commentChar`, "#" by default) and
+    /// everything below the scissors line are ignored.
+    CommitMsg {
+        /// Commit message file, usually .git/COMMIT_EDITMSG
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        /// Fix the commit message in place instead of reporting issues
+        #[arg(long, action = ArgAction::SetTrue)]
+        fix: bool,
+    },
+    /// Write clean into a local git hook, chaining into an existing hook
+    ///
+    /// The hooks directory honors `core.hooksPath` and linked worktrees.
+    InstallHook {
+        /// Which hook to install
+        #[arg(value_enum, default_value = "pre-commit")]
+        hook: hooks::Hook,
+        /// Remove clean from the hook instead
+        #[arg(long, action = ArgAction::SetTrue)]
+        uninstall: bool,
+    },
+    /// Lint files changed by the commits being pushed, for use in a pre-push hook
+    ///
+    /// Reads "<local ref> <local sha> <remote ref> <remote sha>" lines from stdin.
+    PrePush {
+        /// Name of the remote being pushed to
+        remote: Option<String>,
+        /// URL of the remote being pushed to
+        url: Option<String>,
+    },
+    /// Run a language server over stdio, publishing issues as diagnostics
+    ///
+    /// Supports code actions to fix a line or the whole file, and formatting.
+    Lsp,
+    /// List all rules with their default severity and whether they can be fixed
+    Rules,
+    /// Explain a rule, with an example of text it reports and the same text fixed
+    Explain {
+        /// Id of the rule, as listed by `clean rules`
+        #[arg(value_name = "RULE")]
+        rule: String,
+    },
+    /// Restore the files changed by a --fix run, by default the last one
+    ///
+    /// Files changed since the run are not restored unless --force is given.
+    Undo {
+        /// The id of the run, see --list
+        run: Option<String>,
+        /// Restore files even if they were changed since the run
+        #[arg(long, action = ArgAction::SetTrue)]
+        force: bool,
+        /// List the runs that can be undone, newest first
+        #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["run", "force"])]
+        list: bool,
+    },
+    /// Manage the cache of lint results in $XDG_CACHE_HOME/clean
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Remove all cached results
+    Clear,
+}
+
+fn read_files_from(path: &std::path::Path, null: bool) -> anyhow::Result<Vec<PathBuf>> {
+    let data = if path == std::path::Path::new("-") {
+        let mut buf = Vec::new();
+        io::stdin().read_to_end(&mut buf)?;
+        buf
+    } else {
+        match fs::read(path) {
+            Ok(d) => d,
+            Err(e) => {
+                return Err(Failure::Io(format!(
+                    "failed to read file list {}: {}",
+                    path.display(),
+                    e
+                ))
+                .into())
             }
         }
-    }
-    let files = String::from_utf8_lossy(&output.stdout);
-    Ok(files
-        .lines()
-        .map(|l| dir.join(l).to_string_lossy().to_string())
+    };
+    let sep = if null { b'\0' } else { b'\n' };
+    Ok(data
+        .split(|b| *b == sep)
+        .filter(|p| !p.is_empty())
+        .map(|p| PathBuf::from(std::ffi::OsStr::from_bytes(p)))
         .collect())
 }
 
-fn should_ignore(path: &str, ignores: &[String]) -> Result<bool, glob::PatternError> {
-    for pat in ignores {
-        let pat_obj = glob::Pattern::new(pat)?;
-        if pat_obj.matches(path) {
-            return Ok(true);
-        }
-        if pat_obj.matches(
-            PathBuf::from(path)
-                .file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .as_ref(),
-        ) {
-            return Ok(true);
+/// What linting files found, and what fixing them would change.
+#[derive(Debug, Default)]
+struct Linted {
+    /// The issues of the files, those left after fixing them.
+    issues: Vec<Issue>,
+    /// The fixes that were not written, as unified diffs.
+    diffs: Vec<String>,
+    /// The files that were fixed.
+    fixed: Vec<FixedFile>,
+    /// The number of files that could not be fixed, which fails the run.
+    fix_failures: usize,
+}
+
+impl From<Vec<Issue>> for Linted {
+    fn from(issues: Vec<Issue>) -> Self {
+        Linted {
+            issues,
+            ..Linted::default()
         }
     }
-    Ok(false)
 }
 
-fn lint_file(path: &str, content: &str) -> Vec<Issue> {
-    let mut issues = Vec::new();
-    let lines: Vec<&str> = content.split('\n').collect();
-    for (i, line) in lines.iter().enumerate().take(lines.len().saturating_sub(1)) {
-        if line.trim_end().len() != line.len() {
-            issues.push(Issue {
-                issue_type: IssueType::TrailingWhitespace,
-                line: Some(i + 1),
-                file: path.to_string(),
-                message: Some("Trailing whitespace".into()),
-            });
+fn lint_one(
+    cli: &Cli,
+    registry: &Registry,
+    cache: Option<&Cache>,
+    path: &std::path::Path,
+    ws: &WsRule,
+) -> Result<Linted> {
+    let path_str = path.to_string_lossy();
+    if fs::metadata(path).is_ok_and(|md| md.len() > stream::STREAM_THRESHOLD) {
+        return lint_large(cli, registry, path, ws);
+    }
+    let content = match fs::read(path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("failed to read file '{}': {}", path_str, e);
+            return Ok(Linted::default());
         }
+    };
+    let issues = cache::lint(cache, registry, &path_str, &content, ws)?;
+    if issues.is_empty() {
+        return Ok(issues.into());
     }
-    if let Some(last) = lines.last() {
-        if last.trim_end().len() != last.len() {
-            issues.push(Issue {
-                issue_type: IssueType::TrailingWhitespace,
-                line: Some(lines.len()),
-                file: path.to_string(),
-                message: Some("Trailing whitespace".into()),
+    let Ok(content) = std::str::from_utf8(&content) else {
+        warn!(
+            "file '{}' is not a valid UTF-8 text file, skipped",
+            path_str
+        );
+        return Ok(Linted::default());
+    };
+    if cli.fix || cli.check {
+        let fixed = registry.fix(&path_str, content, ws);
+        if cli.dry_run() {
+            let diffs = diff::unified(&path_str, content, &fixed)
+                .into_iter()
+                .collect();
+            return Ok(Linted {
+                issues,
+                diffs,
+                ..Linted::default()
             });
         }
+        if fixed == content {
+            return Ok(issues.into());
+        }
+        let written = match write_fixed(cli, path, |out| out.write_all(fixed.as_bytes())) {
+            Ok(written) => written,
+            Err(e) => return Ok(fix_failed(&path_str, e, issues)),
+        };
+        if !written {
+            return Ok(issues.into());
+        }
+        let left = cache::lint(cache, registry, &path_str, fixed.as_bytes(), ws)?;
+        return Ok(Linted {
+            fixed: vec![FixedFile::new(&path_str, &issues, &left)],
+            issues: left,
+            ..Linted::default()
+        });
     }
-    if !content.ends_with('\n') {
-        issues.push(Issue {
-            issue_type: IssueType::MissingNewline,
-            line: Some(lines.len()),
-            file: path.to_string(),
-            message: Some("Missing newline at end of file".into()),
+    Ok(issues.into())
+}
+
+/// Like [`lint_one`], but streams the file instead of reading it at once.
+fn lint_large(
+    cli: &Cli,
+    registry: &Registry,
+    path: &std::path::Path,
+    ws: &WsRule,
+) -> Result<Linted> {
+    let path_str = path.to_string_lossy();
+    let result = fs::File::open(path)
+        .map_err(clean::Error::from)
+        .and_then(|f| stream::lint_reader(registry, &path_str, f, ws));
+    let issues = match result {
+        Ok(issues) => issues,
+        Err(e @ clean::Error::RuleFailed { .. }) => return Err(e.into()),
+        Err(clean::Error::NotUtf8(_)) => {
+            warn!(
+                "file '{}' is not a valid UTF-8 text file, skipped",
+                path_str
+            );
+            return Ok(Linted::default());
+        }
+        Err(e) => {
+            warn!("failed to read file '{}': {}", path_str, e);
+            return Ok(Linted::default());
+        }
+    };
+    if (cli.fix || cli.check) && !issues.is_empty() {
+        if cli.dry_run() {
+            let diffs = diff_large(registry, path, ws)?.into_iter().collect();
+            return Ok(Linted {
+                issues,
+                diffs,
+                ..Linted::default()
+            });
+        }
+        // Like in memory, a file the fixes do not change is not written.
+        let input = io::BufReader::new(fs::File::open(path)?);
+        if !stream::fix_changes(registry, &path_str, input, ws)? {
+            return Ok(issues.into());
+        }
+        let written = write_fixed(cli, path, |out| {
+            let input = io::BufReader::new(fs::File::open(path)?);
+            stream::fix_reader(registry, &path_str, input, out, ws).map(drop)
+        });
+        let written = match written {
+            Ok(written) => written,
+            Err(e) => return Ok(fix_failed(&path_str, e, issues)),
+        };
+        if !written {
+            return Ok(issues.into());
+        }
+        let left = stream::lint_reader(registry, &path_str, fs::File::open(path)?, ws)?;
+        return Ok(Linted {
+            fixed: vec![FixedFile::new(&path_str, &issues, &left)],
+            issues: left,
+            ..Linted::default()
         });
     }
-    if content.contains("\r\n") {
-        for (i, line) in lines.iter().enumerate() {
-            if line.contains('\r') {
-                issues.push(Issue {
-                    issue_type: IssueType::CrlfLineEnding,
-                    line: Some(i + 1),
-                    file: path.to_string(),
-                    message: Some("Contains CRLF line endings".into()),
-                });
-            }
+    Ok(issues.into())
+}
+
+/// Backs up the file of `target` if asked to, saves it in the journal and
+/// replaces it by what `fill` writes.
+fn fix_target(
+    cli: &Cli,
+    target: &rewrite::Target,
+    fill: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+    if let Some(ref suffix) = cli.backup {
+        let mut backup = target.path.clone().into_os_string();
+        backup.push(suffix);
+        fs::copy(&target.path, backup)?;
+    }
+    // Fixing goes on if the journal can not be written.
+    let saved = if cli.no_journal {
+        None
+    } else {
+        cli.journal.save(&target.path)
+    };
+    rewrite::replace(target, |out| {
+        let mut out = journal::Hashing::new(out);
+        fill(&mut out)?;
+        // Recorded before the file is replaced, so that a crash can not
+        // leave it fixed but missing from the journal.
+        if let Some(saved) = saved {
+            cli.journal.record(saved, out.digest());
         }
+        Ok(())
+    })
+}
+
+/// Returns the diff of the streamed fixes of `path`, which is read at once
+/// to be compared with them.
+fn diff_large(registry: &Registry, path: &std::path::Path, ws: &WsRule) -> Result<Option<String>> {
+    let path_str = path.to_string_lossy();
+    let content = fs::read_to_string(path)?;
+    let mut fixed = Vec::with_capacity(content.len());
+    stream::fix_reader(registry, &path_str, content.as_bytes(), &mut fixed, ws)?;
+    let fixed = String::from_utf8(fixed)?;
+    Ok(diff::unified(&path_str, &content, &fixed))
+}
+
+/// Records that `path` could not be fixed, its `issues` are reported
+/// instead.
+fn fix_failed(path: &str, e: io::Error, issues: Vec<Issue>) -> Linted {
+    error!("failed to fix file '{}': {}", path, e);
+    Linted {
+        issues,
+        fix_failures: 1,
+        ..Linted::default()
     }
-    if !content.is_empty() {
-        let mut n = 0;
-        for c in content.chars().rev() {
-            if c == '\n' || c == '\r' {
-                n += 1;
-            } else {
-                break;
+}
+
+/// Writes the fixes of `path` with [`rewrite::replace`], unless the policy
+/// of the command line skips it. Returns whether the file was fixed.
+fn write_fixed(
+    cli: &Cli,
+    path: &std::path::Path,
+    fill: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<bool> {
+    let target = cli.rewrite_policy().target(path)?;
+    let written = target.map(|t| fix_target(cli, &t, fill)).transpose()?;
+    Ok(written.is_some())
+}
+
+fn jobs(cli: &Cli) -> usize {
+    cli.jobs.map(NonZeroUsize::get).unwrap_or_else(|| {
+        std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+    })
+}
+
+/// Opens the result cache, unless disabled with `--no-cache`.
+fn open_cache(cli: &Cli, config: &Config) -> Option<Cache> {
+    if cli.no_cache {
+        return None;
+    }
+    // Results depend on the custom rules and plugins too, and on the
+    // directory their globs are relative to.
+    let root = config.root().unwrap_or(std::path::Path::new(""));
+    let cache = Cache::open(&format!("{}\0{}", root.display(), config.source()))?;
+    cache.prune();
+    Some(cache)
+}
+
+/// Returns the registry of `config`, with the rules not chosen with
+/// `--select` and `--skip` turned off.
+fn load_registry(cli: &Cli, config: &Config) -> Result<Registry> {
+    let mut registry = config.registry();
+    if let Some(id) = cli
+        .select
+        .iter()
+        .chain(&cli.skip)
+        .find(|id| registry.get(id).is_none())
+    {
+        return Err(Failure::Usage(format!("unknown rule '{}', see `clean rules`", id)).into());
+    }
+    let mut off = Override::new(Vec::new())?;
+    if !cli.select.is_empty() {
+        for rule in registry.rules() {
+            if !cli.select.iter().any(|id| id == rule.id()) {
+                off = off.rule(rule.id(), None);
             }
         }
-        if n > 1 {
-            issues.push(Issue {
-                issue_type: IssueType::MultipleBlankLinesEof,
-                line: Some(lines.len()),
-                file: path.to_string(),
-                message: Some("Multiple blank lines at end of file".into()),
-            });
+    }
+    for id in &cli.skip {
+        off = off.rule(id, None);
+    }
+    // Added last, so that it takes precedence over the configuration.
+    registry.add_override(off);
+    Ok(registry)
+}
+
+/// Loads the configuration given with `--config`, or the closest
+/// `.clean.toml` above the first path linted, or else the current directory.
+fn load_config(cli: &Cli) -> Result<Config> {
+    let target = cli
+        .paths
+        .first()
+        .map(PathBuf::as_path)
+        .or(cli.stdin_filename.as_deref().map(std::path::Path::new))
+        .unwrap_or(std::path::Path::new("."));
+    let start = match fs::canonicalize(target) {
+        Ok(path) if path.is_dir() => path,
+        Ok(path) => path.parent().map(PathBuf::from).unwrap_or_default(),
+        Err(_) => std::env::current_dir()?,
+    };
+    load_config_from(cli, &start)
+}
+
+/// Loads the configuration given with `--config`, or the closest
+/// `.clean.toml` above the directory `start`.
+fn load_config_from(cli: &Cli, start: &std::path::Path) -> Result<Config> {
+    let path = match cli.config {
+        Some(ref path) => path.clone(),
+        None => match Config::find(start) {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        },
+    };
+    Ok(Config::load(&path)?)
+}
+
+/// Lints `files` on a pool of worker threads.
+///
+/// Issues are sorted by file and line, and diffs by file, so the report does
+/// not depend on which worker finished first. Fails if a rule fails on any
+/// file.
+fn lint_files(
+    cli: &Cli,
+    registry: &Registry,
+    cache: Option<&Cache>,
+    files: &[(PathBuf, WsRule)],
+) -> Result<Linted> {
+    let workers = jobs(cli).min(files.len()).max(1);
+    let next = AtomicUsize::new(0);
+    let results = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut linted = Linted::default();
+                    while let Some((path, ws)) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let Linted {
+                            issues,
+                            diffs,
+                            fixed,
+                            fix_failures,
+                        } = lint_one(cli, registry, cache, path, ws)?;
+                        linted.issues.extend(issues);
+                        linted.diffs.extend(diffs);
+                        linted.fixed.extend(fixed);
+                        linted.fix_failures += fix_failures;
+                    }
+                    Ok(linted)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Result<Vec<Linted>>>()
+    })?;
+    let mut linted = Linted::default();
+    for result in results {
+        linted.issues.extend(result.issues);
+        linted.diffs.extend(result.diffs);
+        linted.fixed.extend(result.fixed);
+        linted.fix_failures += result.fix_failures;
+    }
+    linted
+        .issues
+        .sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    // Each diff starts with its "diff --git a/<path>" header.
+    linted.diffs.sort();
+    linted.fixed.sort_by(|a, b| a.file.cmp(&b.file));
+    Ok(linted)
+}
+
+/// Walks `dir` in parallel and returns all regular files below it, sorted.
+fn walk_dir(cli: &Cli, dir: &std::path::Path) -> Vec<PathBuf> {
+    let files = Mutex::new(Vec::new());
+    WalkBuilder::new(dir)
+        .standard_filters(false)
+        .threads(jobs(cli))
+        .build_parallel()
+        .run(|| {
+            Box::new(|entry| {
+                if let Ok(entry) = entry {
+                    if entry.file_type().is_some_and(|t| t.is_file()) {
+                        files.lock().unwrap().push(entry.into_path());
+                    }
+                }
+                WalkState::Continue
+            })
+        });
+    let mut files = files.into_inner().unwrap();
+    files.sort();
+    files
+}
+
+/// Lints git blobs given as `(path, object)` pairs, such as staged content.
+fn lint_blobs(
+    cli: &Cli,
+    registry: &Registry,
+    cache: Option<&Cache>,
+    top: &std::path::Path,
+    specs: Vec<(String, String)>,
+) -> Result<Vec<Issue>> {
+    let mut reader = git::BlobReader::spawn(top)?;
+    let mut resolver = RuleResolver::new(top)?;
+    // The paths of blobs are relative to the top of the repository.
+    let mut registry = registry.clone();
+    registry.relative_to(top);
+    let registry = &registry;
+    let mut all_issues = Vec::new();
+    for (path, spec) in specs {
+        if ignored(cli, &path) {
+            continue;
+        }
+        let Some(ws) = resolver.rule_for(&path)? else {
+            continue;
+        };
+        let Some(blob) = reader.read(&spec)? else {
+            continue;
+        };
+        let issues = cache::lint(cache, registry, &path, &blob, &ws)?;
+        if !issues.is_empty() && std::str::from_utf8(&blob).is_err() {
+            warn!("file '{}' is not a valid UTF-8 text file, skipped", path);
+            continue;
         }
+        all_issues.extend(issues);
     }
-    issues
+    Ok(all_issues)
 }
 
-fn main() -> Result<()> {
+fn lint_pre_push(cli: &Cli, config: &Config, remote: Option<&str>) -> Result<()> {
+    let top = git::show_toplevel(std::path::Path::new("."))?;
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    let mut specs = Vec::new();
+    for line in input.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [_, local_sha, _, remote_sha] = fields[..] else {
+            continue;
+        };
+        // Deleting a remote ref pushes nothing.
+        if local_sha.trim_start_matches('0').is_empty() {
+            continue;
+        }
+        let range = format!("{}..{}", remote_sha, local_sha);
+        let remotes = format!("--remotes={}", remote.unwrap_or("*"));
+        let revs: Vec<&str> = if remote_sha.trim_start_matches('0').is_empty() {
+            // A new branch: every commit not on the remote yet.
+            vec![local_sha, "--not", &remotes]
+        } else {
+            vec![&range]
+        };
+        for file in git::changed_files(&top, &revs)? {
+            specs.push((file.clone(), format!("{}:{}", local_sha, file)));
+        }
+    }
+    specs.sort();
+    specs.dedup_by(|a, b| a.0 == b.0);
+    let cache = open_cache(cli, config);
+    report(
+        cli,
+        lint_blobs(cli, &load_registry(cli, config)?, cache.as_ref(), &top, specs)?.into(),
+    )
+}
+
+fn lint_stdin(cli: &Cli, registry: &Registry) -> Result<()> {
+    let name = cli.stdin_filename.as_deref().unwrap_or("<stdin>");
+    let ws = if ignored(cli, name) {
+        None
+    } else {
+        let mut resolver = RuleResolver::new(std::path::Path::new("."))?;
+        // The name may be outside of the repository, or made up.
+        resolver.rule_for(name).unwrap_or_else(|e| {
+            warn!(
+                "failed to resolve the whitespace rule of '{}', using core.whitespace: {}",
+                name, e
+            );
+            Some(resolver.core())
+        })
+    };
+    let mut buf = Vec::new();
+    io::stdin().read_to_end(&mut buf)?;
+    let content = match std::str::from_utf8(&buf) {
+        Ok(c) => Some(c),
+        Err(_) => {
+            warn!("stdin '{}' is not a valid UTF-8 text file, skipped", name);
+            None
+        }
+    };
+    if cli.dry_run() {
+        let (issues, diffs) = match (content, ws) {
+            (Some(c), Some(ws)) => (
+                registry.lint(name, c.as_bytes(), &ws)?,
+                diff::unified(name, c, &registry.fix(name, c, &ws))
+                    .into_iter()
+                    .collect(),
+            ),
+            _ => Default::default(),
+        };
+        return report_dry_run(
+            cli,
+            Linted {
+                issues,
+                diffs,
+                ..Linted::default()
+            },
+        );
+    }
+    if cli.fix {
+        // Act as a filter: ignored or non-text input is passed through as is.
+        let mut out = open_output(cli)?;
+        match (content, ws) {
+            (Some(c), Some(ws)) => out.write_all(registry.fix(name, c, &ws).as_bytes())?,
+            _ => out.write_all(&buf)?,
+        }
+        out.flush()?;
+        return Ok(());
+    }
+    let issues = match (content, ws) {
+        (Some(c), Some(ws)) => registry.lint(name, c.as_bytes(), &ws)?,
+        _ => Vec::new(),
+    };
+    report(cli, issues.into())
+}
+
+fn main() -> ExitCode {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
-    let cli = Cli::parse();
-    let mut all_issues = Vec::new();
-    for dir in &cli.dirs {
-        if !dir.exists() {
-            anyhow::bail!("Directory not found: {}", dir.display());
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let failure = Failure::from(e);
+            // Issues are in the report already.
+            if !matches!(failure, Failure::Issues) {
+                error!("{}", failure);
+            }
+            failure.code()
+        }
+    }
+}
+
+fn run(mut cli: Cli) -> Result<()> {
+    if let Err(e) = should_ignore("", &cli.ignore) {
+        return Err(Failure::Usage(format!("Invalid glob pattern: {}", e)).into());
+    }
+    match cli.command {
+        Some(Commands::FilterProcess) => {
+            return filter::run(&load_registry(&cli, &load_config(&cli)?)?, &cli.ignore)
+        }
+        Some(Commands::CommitMsg { ref file, fix }) => {
+            let registry = load_registry(&cli, &load_config(&cli)?)?;
+            return report(&cli, commit_msg::run(&registry, file, fix)?.into());
+        }
+        Some(Commands::InstallHook { hook, uninstall }) => {
+            return hooks::install(std::path::Path::new("."), hook, uninstall)
         }
-        let in_git_repo = is_git_repo(dir);
+        Some(Commands::PrePush { ref remote, .. }) => {
+            return lint_pre_push(&cli, &load_config(&cli)?, remote.as_deref())
+        }
+        Some(Commands::Lsp) => {
+            // The configuration is looked up from the workspace of the client.
+            let load = |root: Option<&std::path::Path>| match root {
+                Some(root) => load_registry(&cli, &load_config_from(&cli, root)?),
+                None => load_registry(&cli, &load_config(&cli)?),
+            };
+            return lsp::run(load, &cli.ignore)
+        }
+        Some(Commands::Rules) => {
+            return catalog::list(&load_config(&cli)?.registry(), &mut io::stdout())
+        }
+        Some(Commands::Explain { ref rule }) => {
+            return catalog::explain(&load_config(&cli)?.registry(), rule, &mut io::stdout())
+        }
+        Some(Commands::Undo {
+            ref run,
+            force,
+            list,
+        }) => {
+            if list {
+                return journal::list(&mut io::stdout());
+            }
+            return journal::undo(run.as_deref(), force);
+        }
+        Some(Commands::Cache {
+            command: CacheCommand::Clear,
+        }) => {
+            return match Cache::open("") {
+                Some(cache) => cache.clear(),
+                None => Ok(()),
+            }
+        }
+        None => {}
+    }
+    let config = load_config(&cli)?;
+    let registry = load_registry(&cli, &config)?;
+    if cli.staged {
+        let top = git::show_toplevel(std::path::Path::new("."))?;
+        let files = git::staged_files(&top)?;
+        let specs = files.iter().map(|f| (f.clone(), format!(":{}", f))).collect();
+        let cache = open_cache(&cli, &config);
+        return report(
+            &cli,
+            lint_blobs(&cli, &registry, cache.as_ref(), &top, specs)?.into(),
+        );
+    }
+    if cli.stdin {
+        return lint_stdin(&cli, &registry);
+    }
+    let mut files = Vec::new();
+    if let Some(ref list) = cli.files_from {
+        files = read_files_from(list, cli.null)?;
+    } else if cli.paths.is_empty() {
+        cli.paths.push(PathBuf::from("."));
+    }
+    let mut dirs = Vec::new();
+    for path in &cli.paths {
+        if !path.exists() {
+            return Err(Failure::Usage(format!("Path not found: {}", path.display())).into());
+        }
+        if path.is_dir() {
+            dirs.push(path.clone());
+        } else {
+            files.push(path.clone());
+        }
+    }
+    cli.roots = dirs
+        .iter()
+        .chain([&PathBuf::from(".")])
+        .filter_map(|dir| fs::canonicalize(dir).ok())
+        .collect();
+    let jobs = collect_jobs(&cli, &files, &dirs)?;
+    let cache = open_cache(&cli, &config);
+    if cli.watch {
+        return watch::run(&cli, &registry, cache.as_ref(), &files, &dirs, jobs);
+    }
+    let linted = lint_files(&cli, &registry, cache.as_ref(), &jobs)?;
+    if let Some(id) = cli.journal.id() {
+        info!("fixed files were saved as run {}, see `clean undo`", id);
+    }
+    if cli.dry_run() {
+        return report_dry_run(&cli, linted);
+    }
+    report(&cli, linted)
+}
+
+/// Whether `path` matches an `--ignore` pattern, which were validated in
+/// [`run`].
+fn ignored(cli: &Cli, path: &str) -> bool {
+    should_ignore(path, &cli.ignore).unwrap_or(false)
+}
+
+/// Decides which files found below a linted directory are linted, and with
+/// which rule.
+struct DirFilter {
+    dir: PathBuf,
+    tracked_files: Option<HashSet<String>>,
+    resolver: RuleResolver,
+}
+
+impl DirFilter {
+    fn new(cli: &Cli, dir: &std::path::Path) -> Result<Self> {
+        let in_git_repo = git::is_git_repo(dir);
         let use_git = match cli.git {
             None => in_git_repo,
             Some(true) => true,
@@ -201,146 +927,188 @@ fn main() -> Result<()> {
         };
         let mut tracked_files = None;
         if use_git {
-            tracked_files = Some(git_tracked_files(dir)?);
+            tracked_files = Some(git::git_tracked_files(dir)?);
         }
-        for entry in WalkDir::new(dir)
-            .into_iter()
-            .filter_map(Result::ok)
-            .filter(|e| e.file_type().is_file())
-        {
-            let path = entry.path();
-            let path_str = path.to_string_lossy();
-            if let Some(ref files) = tracked_files {
-                if !files.contains(&path.to_string_lossy().to_string()) {
-                    continue;
-                }
-            }
-            let rel_path = path.strip_prefix(dir).unwrap_or(path);
-            let rel_str = rel_path.to_string_lossy();
-            match should_ignore(&rel_str, &cli.ignore) {
-                Ok(true) => continue,
-                Ok(false) => {}
-                Err(e) => {
-                    error!("Invalid glob pattern: {}", e);
-                    std::process::exit(1);
-                }
-            }
-            let content = match fs::read_to_string(path) {
-                Ok(c) => c,
-                Err(e) => {
-                    warn!("failed to read file '{}': {}", path_str, e);
-                    continue;
-                }
-            };
-            if !content.is_char_boundary(content.len()) {
-                warn!(
-                    "file '{}' is not a valid UTF-8 text file, skipped",
-                    path_str
-                );
-                continue;
-            }
-            let issues = lint_file(&path_str, &content);
-            if cli.fix && !issues.is_empty() {
-                let fixed = fix_file(&content);
-                if let Err(e) = fs::write(path, &fixed) {
-                    warn!("failed to fix file '{}': {}", path_str, e);
-                    all_issues.extend(issues);
-                }
-            } else {
-                all_issues.extend(issues);
+        Ok(DirFilter {
+            dir: dir.to_path_buf(),
+            tracked_files,
+            resolver: RuleResolver::new(dir)?,
+        })
+    }
+
+    /// Returns `path` relative to the directory, which it starts with, or
+    /// `None` if it is not linted.
+    fn linted(&self, cli: &Cli, path: &std::path::Path) -> Option<String> {
+        if let Some(ref files) = self.tracked_files {
+            if !files.contains(&path.to_string_lossy().to_string()) {
+                return None;
             }
         }
+        let rel_path = path.strip_prefix(&self.dir).unwrap_or(path);
+        let rel_str = rel_path.to_string_lossy();
+        if ignored(cli, &rel_str) {
+            return None;
+        }
+        Some(rel_str.into_owned())
     }
-    if cli.fix {
-        if all_issues.is_empty() {
-            return Ok(());
+
+    /// Returns the rule for `path`, which starts with the directory, or
+    /// `None` if it is not linted.
+    fn rule_for(&mut self, cli: &Cli, path: &std::path::Path) -> Result<Option<WsRule>> {
+        let Some(rel_path) = self.linted(cli, path) else {
+            return Ok(None);
+        };
+        Ok(self.resolver.rule_for(&rel_path)?)
+    }
+
+    /// Returns the linted `paths` with their rules, looking up their
+    /// attributes at once.
+    fn rules_for(&mut self, cli: &Cli, paths: Vec<PathBuf>) -> Result<Vec<(PathBuf, WsRule)>> {
+        let linted: Vec<(PathBuf, String)> = paths
+            .into_iter()
+            .filter_map(|p| self.linted(cli, &p).map(|rel| (p, rel)))
+            .collect();
+        let rel_paths: Vec<&str> = linted.iter().map(|(_, rel)| rel.as_str()).collect();
+        let rules = self.resolver.rules_for(&rel_paths)?;
+        Ok(linted
+            .into_iter()
+            .zip(rules)
+            .filter_map(|((path, _), rule)| Some((path, rule?)))
+            .collect())
+    }
+}
+
+fn collect_jobs(cli: &Cli, files: &[PathBuf], dirs: &[PathBuf]) -> Result<Vec<(PathBuf, WsRule)>> {
+    // Explicitly given files bypass directory walking and git tracking,
+    // but still respect ignore rules.
+    let mut listed = Vec::new();
+    for path in files {
+        // Only listed files can be missing, lists of changed files often
+        // include deleted ones.
+        if !path.exists() {
+            warn!("listed file not found, skipped: {}", path.display());
+            continue;
+        }
+        if ignored(cli, &path.to_string_lossy()) {
+            continue;
         }
-        anyhow::bail!("failed to fix some files");
+        listed.push(path.clone());
+    }
+    let rules = RuleResolvers::default().rules_for(&listed)?;
+    let mut jobs: Vec<(PathBuf, WsRule)> = listed
+        .into_iter()
+        .zip(rules)
+        .filter_map(|(path, rule)| Some((path, rule?)))
+        .collect();
+    for dir in dirs {
+        let mut filter = DirFilter::new(cli, dir)?;
+        jobs.extend(filter.rules_for(cli, walk_dir(cli, dir))?);
     }
-    // Output
-    let mut out: Box<dyn Write> = if let Some(ref p) = cli.output {
+    Ok(jobs)
+}
+
+fn open_output(cli: &Cli) -> Result<Box<dyn Write>> {
+    let out: Box<dyn Write> = if let Some(ref p) = cli.output {
         match fs::File::create(p) {
             Ok(f) => Box::new(f),
             Err(e) => {
-                if let Ok(md) = fs::metadata(p) {
-                    if md.is_dir() {
-                        anyhow::bail!("output path is a directory: {}", p.display());
-                    } else {
-                        anyhow::bail!("failed to write output file {}: {}", p.display(), e);
-                    }
+                let message = if fs::metadata(p).is_ok_and(|md| md.is_dir()) {
+                    format!("output path is a directory: {}", p.display())
                 } else {
-                    anyhow::bail!("failed to write output file {}: {}", p.display(), e);
-                }
+                    format!("failed to write output file {}: {}", p.display(), e)
+                };
+                return Err(Failure::Io(message).into());
             }
         }
     } else {
         Box::new(io::stdout())
     };
-    if cli.json {
-        for i in &mut all_issues {
-            i.message = None;
-        }
-        serde_json::to_writer_pretty(&mut out, &all_issues)?;
-        if all_issues.is_empty() {
-            return Ok(());
+    Ok(out)
+}
+
+fn report(cli: &Cli, linted: Linted) -> Result<()> {
+    let mut out = open_output(cli)?;
+    let fail_on = cli.fail_on.severity();
+    let found = linted.issues.iter().any(|i| i.severity >= fail_on);
+    write_report(cli, &mut out, linted.issues, &linted.fixed)?;
+    let failures = linted.fix_failures;
+    if failures > 0 {
+        let plural = if failures == 1 { "" } else { "s" };
+        let message = format!("failed to fix {} file{}", failures, plural);
+        return Err(Failure::Fix(message).into());
+    }
+    if found {
+        return Err(Failure::Issues.into());
+    }
+    Ok(())
+}
+
+/// Reports what fixing would change: the diffs with `--diff`, the issues
+/// otherwise. Fails if any file would be changed.
+fn report_dry_run(cli: &Cli, linted: Linted) -> Result<()> {
+    let mut out = open_output(cli)?;
+    if cli.diff {
+        for diff in &linted.diffs {
+            out.write_all(diff.as_bytes())?;
         }
-        anyhow::bail!("issues found");
+        out.flush()?;
+    } else {
+        write_report(cli, &mut out, linted.issues, &[])?;
+    }
+    if !linted.diffs.is_empty() {
+        return Err(Failure::Issues.into());
     }
-    if cli.yaml {
+    Ok(())
+}
+
+/// Writes the report of `all_issues`, and with `--fix` of the `fixed` files.
+fn write_report(
+    cli: &Cli,
+    out: &mut dyn Write,
+    mut all_issues: Vec<Issue>,
+    fixed: &[FixedFile],
+) -> Result<()> {
+    if cli.json || cli.yaml {
         for i in &mut all_issues {
-            i.message = None;
+            i.message = i.message.take().filter(|m| m != i.issue_type.message());
         }
-        serde_yaml::to_writer(&mut out, &all_issues)?;
-        if all_issues.is_empty() {
-            return Ok(());
+        // With --fix, the issues are those left, next to what was fixed.
+        let fix_report = summary::Report::new(fixed, &all_issues);
+        match (cli.json, cli.fix) {
+            (true, true) => serde_json::to_writer_pretty(&mut *out, &fix_report)?,
+            (true, false) => serde_json::to_writer_pretty(&mut *out, &all_issues)?,
+            (false, true) => serde_yaml::to_writer(&mut *out, &fix_report)?,
+            (false, false) => serde_yaml::to_writer(&mut *out, &all_issues)?,
         }
-        anyhow::bail!("issues found");
+        out.flush()?;
+        return Ok(());
     }
     writeln!(out, "# Clean report\n")?;
-    for dir in &cli.dirs {
-        let mut cur_file = "";
-        for issue in all_issues
-            .iter()
-            .filter(|i| i.file.starts_with(&*dir.to_string_lossy()))
-        {
-            if issue.file != cur_file {
-                if !cur_file.is_empty() {
-                    writeln!(out)?;
-                }
-                writeln!(out, "## {}\n", issue.file)?;
-                cur_file = &issue.file;
+    let fix_report = summary::Report::new(fixed, &all_issues);
+    fix_report.write_fixed(out)?;
+    let mut cur_file = "";
+    for issue in &all_issues {
+        if issue.file != cur_file {
+            if !cur_file.is_empty() {
+                writeln!(out)?;
             }
-            writeln!(
-                out,
-                "- **Line:** `{}` {}",
-                issue.line.unwrap_or(0),
-                issue.message.as_deref().unwrap_or("")
-            )?;
+            writeln!(out, "## {}\n", issue.file)?;
+            cur_file = &issue.file;
         }
-        writeln!(out)?;
+        writeln!(
+            out,
+            "- **Line:** `{}` {}: {}",
+            issue.line.unwrap_or(0),
+            issue.severity,
+            issue.message.as_deref().unwrap_or("")
+        )?;
     }
-    if all_issues.is_empty() {
+    writeln!(out)?;
+    if cli.fix {
+        fix_report.write_end(out)?;
+    } else if all_issues.is_empty() {
         writeln!(out, "No lint issues found.\n")?;
-        return Ok(());
-    }
-    anyhow::bail!("issues found");
-}
-
-fn fix_file(content: &str) -> String {
-    if content.is_empty() {
-        return String::new();
-    }
-    // Step 1: CRLF → LF
-    let content = content.replace("\r\n", "\n");
-    // Step 2: Trim trailing whitespace on each line
-    let mut lines: Vec<String> = content.split('\n').map(|l| l.trim_end().to_string()).collect();
-    // split on trailing \n produces an empty string at the end;
-    // keep it as a marker for the trailing newline
-    // Step 3: Remove multiple trailing blank lines — ensure exactly one trailing \n
-    while lines.len() > 1 && lines.last().map_or(false, |l| l.is_empty()) {
-        lines.pop();
     }
-    // Step 4: Ensure file ends with \n
-    lines.push(String::new());
-    lines.join("\n")
+    out.flush()?;
+    Ok(())
 }
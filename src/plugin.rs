@@ -0,0 +1,424 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Rules implemented by external commands, as defined in the configuration
+//! file.
+//!
+//! A plugin is run once for each file it applies to. It reads a JSON request
+//! from stdin:
+//!
+//! ```json
+//! {"path": "src/a.sh", "content": "echo $1\n", "settings": {}}
+//! ```
+//!
+//! with `content_path`, the path of a file with the content, instead of
+//! `content` if the plugin reads files itself, or for files too large to be
+//! read at once. It prints a JSON response to stdout:
+//!
+//! ```json
+//! {
+//!   "issues": [{"line": 1, "message": "Double quote to prevent globbing"}],
+//!   "edits": [{"line": 1, "text": "echo \"$1\""}]
+//! }
+//! ```
+//!
+//! Both fields are optional, and a plain list of issues is a valid response
+//! too. Edits replace whole lines, without their line terminator, and are
+//! only applied by `--fix` if the plugin is configured as fixable.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use wait_timeout::ChildExt;
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::rule::{self, FileInfo, Reporter, Rule, Severity};
+use crate::whitespace::WsRule;
+use crate::{Error, Result};
+
+/// How a plugin gets the content of a file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Input {
+    /// In the `content` field of the request.
+    #[default]
+    Content,
+    /// As the path of a file in the `content_path` field of the request.
+    Path,
+}
+
+/// The time a plugin may run for each file, unless configured otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The number of responses kept, so that fixing right after linting does
+/// not run plugins again.
+const MEMO_SIZE: usize = 16;
+
+#[derive(serde::Serialize)]
+struct Request<'a> {
+    path: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_path: Option<PathBuf>,
+    settings: &'a serde_json::Value,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct Response {
+    #[serde(default)]
+    issues: Vec<PluginIssue>,
+    #[serde(default)]
+    edits: Vec<Edit>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum AnyResponse {
+    Issues(Vec<PluginIssue>),
+    Full(Response),
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PluginIssue {
+    line: usize,
+    message: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Edit {
+    line: usize,
+    text: String,
+}
+
+/// A rule that runs an external command on each file.
+pub struct PluginRule {
+    id: String,
+    message: String,
+    command: Vec<String>,
+    severity: Severity,
+    files: Vec<String>,
+    input: Input,
+    settings: serde_json::Value,
+    timeout: Duration,
+    fixable: bool,
+    /// Recent responses by a hash of path and content.
+    memo: Mutex<Vec<(u128, Arc<Response>)>>,
+}
+
+impl fmt::Debug for PluginRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PluginRule")
+            .field("id", &self.id)
+            .field("message", &self.message)
+            .field("command", &self.command)
+            .field("severity", &self.severity)
+            .field("files", &self.files)
+            .field("input", &self.input)
+            .field("settings", &self.settings)
+            .field("timeout", &self.timeout)
+            .field("fixable", &self.fixable)
+            .finish()
+    }
+}
+
+impl Clone for PluginRule {
+    fn clone(&self) -> Self {
+        PluginRule {
+            id: self.id.clone(),
+            message: self.message.clone(),
+            command: self.command.clone(),
+            severity: self.severity,
+            files: self.files.clone(),
+            input: self.input,
+            settings: self.settings.clone(),
+            timeout: self.timeout,
+            fixable: self.fixable,
+            memo: Mutex::default(),
+        }
+    }
+}
+
+impl PluginRule {
+    /// Creates a rule running `command`, a program and its arguments.
+    ///
+    /// `message` describes the rule, and is the message of issues the
+    /// plugin reports without one.
+    pub fn new(id: &str, message: &str, command: Vec<String>) -> Result<Self> {
+        rule::check_id(id)?;
+        if command.is_empty() {
+            return Err(Error::InvalidRule {
+                id: id.to_string(),
+                message: "the command is empty".into(),
+            });
+        }
+        Ok(PluginRule {
+            id: id.to_string(),
+            message: message.to_string(),
+            command,
+            severity: Severity::Error,
+            files: Vec::new(),
+            input: Input::default(),
+            settings: serde_json::Value::Object(Default::default()),
+            timeout: DEFAULT_TIMEOUT,
+            fixable: false,
+            memo: Mutex::default(),
+        })
+    }
+
+    /// Sets the default severity, [`Severity::Error`] if not set.
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    /// Only runs the plugin on files matching one of the glob patterns
+    /// `files`, matched like ignore patterns. All files are checked if empty.
+    pub fn files(mut self, files: Vec<String>) -> Result<Self> {
+        rule::check_files(&self.id, &files)?;
+        self.files = files;
+        Ok(self)
+    }
+
+    /// Sets how the plugin gets the content, [`Input::Content`] if not set.
+    pub fn input(mut self, input: Input) -> Self {
+        self.input = input;
+        self
+    }
+
+    /// Sets the `settings` field of requests, an empty object if not set.
+    pub fn settings(mut self, settings: serde_json::Value) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Sets the time the plugin may run for each file, [`DEFAULT_TIMEOUT`]
+    /// if not set.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Applies the edits of the plugin when fixing.
+    pub fn fixable(mut self, fixable: bool) -> Self {
+        self.fixable = fixable;
+        self
+    }
+
+    /// Runs the plugin on the file at `path`, or returns a recent response
+    /// for the same content.
+    ///
+    /// Streamed files have no `content` and are passed by path.
+    fn run(&self, path: &str, content: Option<&str>) -> Result<Arc<Response>, String> {
+        let key = content.map(|content| {
+            let mut hasher = Xxh3::new();
+            hasher.update(path.as_bytes());
+            hasher.update(b"\0");
+            hasher.update(content.as_bytes());
+            hasher.digest128()
+        });
+        if let Some(key) = key {
+            let memo = self.memo.lock().unwrap();
+            if let Some((_, response)) = memo.iter().find(|(k, _)| *k == key) {
+                return Ok(response.clone());
+            }
+        }
+        let response = Arc::new(match (content, self.input) {
+            (Some(content), Input::Content) => self.request(path, Some(content), None)?,
+            (Some(content), Input::Path) => {
+                let tmp = TempFile::new(content).map_err(|e| e.to_string())?;
+                self.request(path, None, Some(tmp.0.clone()))?
+            }
+            (None, _) => self.request(path, None, Some(PathBuf::from(path)))?,
+        });
+        if let Some(key) = key {
+            let mut memo = self.memo.lock().unwrap();
+            if memo.len() == MEMO_SIZE {
+                memo.remove(0);
+            }
+            memo.push((key, response.clone()));
+        }
+        Ok(response)
+    }
+
+    fn request(
+        &self,
+        path: &str,
+        content: Option<&str>,
+        content_path: Option<PathBuf>,
+    ) -> Result<Response, String> {
+        let request = serde_json::to_vec(&Request {
+            path,
+            content,
+            content_path,
+            settings: &self.settings,
+        })
+        .map_err(|e| e.to_string())?;
+        let command = self.command.join(" ");
+        let mut child = Command::new(&self.command[0])
+            .args(&self.command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to run `{}`: {}", command, e))?;
+        let mut stdin = child.stdin.take().unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+        let mut stderr = child.stderr.take().unwrap();
+        // Pipes are served by threads, so that a plugin blocking on any of
+        // them cannot block clean.
+        let writer = std::thread::spawn(move || match stdin.write_all(&request) {
+            // The plugin does not have to read the whole request.
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            result => result,
+        });
+        let out_reader = std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            stdout.read_to_end(&mut buf).map(|_| buf)
+        });
+        let err_reader = std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            stderr.read_to_end(&mut buf).map(|_| buf)
+        });
+        let status = match child.wait_timeout(self.timeout) {
+            Ok(Some(status)) => status,
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "`{}` timed out after {}s",
+                    command,
+                    self.timeout.as_secs_f64()
+                ));
+            }
+            Err(e) => return Err(format!("failed to wait for `{}`: {}", command, e)),
+        };
+        let write_result = writer.join().unwrap();
+        let out = out_reader.join().unwrap();
+        let err = err_reader.join().unwrap().unwrap_or_default();
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&err);
+            let stderr = stderr.trim();
+            return Err(if stderr.is_empty() {
+                format!("`{}` {}", command, status)
+            } else {
+                format!("`{}` {}: {}", command, status, stderr)
+            });
+        }
+        write_result.map_err(|e| format!("failed to write to `{}`: {}", command, e))?;
+        let out = out.map_err(|e| format!("failed to read from `{}`: {}", command, e))?;
+        match serde_json::from_slice(&out) {
+            Ok(AnyResponse::Issues(issues)) => Ok(Response {
+                issues,
+                edits: Vec::new(),
+            }),
+            Ok(AnyResponse::Full(response)) => Ok(response),
+            Err(e) => Err(format!("invalid response of `{}`: {}", command, e)),
+        }
+    }
+}
+
+/// A temporary file holding content for a plugin, removed when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(content: &str) -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "clean-plugin-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?
+            .write_all(content.as_bytes())?;
+        Ok(TempFile(path))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+impl Rule for PluginRule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn description(&self) -> &str {
+        &self.message
+    }
+
+    fn default_severity(&self) -> Severity {
+        self.severity
+    }
+
+    fn applies_to(&self, path: &str) -> bool {
+        rule::matches_files(&self.files, path)
+    }
+
+    fn check_file(&self, file: &FileInfo<'_>, _ws: &WsRule, reporter: &mut Reporter<'_>) {
+        let content = match file.content.map(std::str::from_utf8) {
+            Some(Ok(content)) => Some(content),
+            // Files that are not text are not handed to plugins.
+            Some(Err(_)) => return,
+            None => None,
+        };
+        match self.run(file.path, content) {
+            Ok(response) => {
+                for issue in &response.issues {
+                    let line = issue.line.max(1);
+                    match issue.message {
+                        Some(ref message) => reporter.report_message(line, message.as_str()),
+                        None => reporter.report(line),
+                    }
+                }
+            }
+            Err(message) => reporter.fail(message),
+        }
+    }
+
+    fn fixable(&self) -> bool {
+        self.fixable
+    }
+
+    fn fix_content(&self, path: &str, content: &str, _ws: &WsRule) -> Option<String> {
+        if !self.fixable {
+            return None;
+        }
+        // A failing plugin was reported when linting, it fixes nothing.
+        let response = self.run(path, Some(content)).ok()?;
+        if response.edits.is_empty() {
+            return None;
+        }
+        let mut lines: Vec<(&str, &str)> = content
+            .split_inclusive('\n')
+            .map(|line| {
+                let text = line.strip_suffix('\n').unwrap_or(line);
+                let text = text.strip_suffix('\r').unwrap_or(text);
+                (text, &line[text.len()..])
+            })
+            .collect();
+        for edit in &response.edits {
+            if let Some(line) = edit.line.checked_sub(1).and_then(|i| lines.get_mut(i)) {
+                line.0 = &edit.text;
+            }
+        }
+        Some(
+            lines
+                .into_iter()
+                .flat_map(|(text, end)| [text, end])
+                .collect(),
+        )
+    }
+}
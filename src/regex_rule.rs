@@ -0,0 +1,107 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Rules that report lines matching a regular expression, as defined in the
+//! configuration file.
+
+use regex::bytes::Regex;
+
+use crate::rule::{self, FixedLine, Line, Rule, Severity};
+use crate::whitespace::WsRule;
+use crate::{Error, Result};
+
+/// A rule reporting each line that matches a pattern, optionally replacing
+/// the matches to fix it.
+///
+/// Patterns are matched against single lines, without the line terminator.
+#[derive(Debug, Clone)]
+pub struct RegexRule {
+    id: String,
+    message: String,
+    pattern: Regex,
+    severity: Severity,
+    files: Vec<String>,
+    replacement: Option<String>,
+}
+
+impl RegexRule {
+    /// Creates a rule reporting lines that match `pattern` with `message`.
+    ///
+    /// Ids may only contain ASCII letters, digits, `_` and `-`.
+    pub fn new(id: &str, message: &str, pattern: &str) -> Result<Self> {
+        rule::check_id(id)?;
+        let pattern = Regex::new(pattern).map_err(|e| Error::InvalidRule {
+            id: id.to_string(),
+            message: e.to_string(),
+        })?;
+        Ok(RegexRule {
+            id: id.to_string(),
+            message: message.to_string(),
+            pattern,
+            severity: Severity::Error,
+            files: Vec::new(),
+            replacement: None,
+        })
+    }
+
+    /// Sets the default severity, [`Severity::Error`] if not set.
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    /// Only checks files matching one of the glob patterns `files`, matched
+    /// like ignore patterns. All files are checked if empty.
+    pub fn files(mut self, files: Vec<String>) -> Result<Self> {
+        rule::check_files(&self.id, &files)?;
+        self.files = files;
+        Ok(self)
+    }
+
+    /// Makes the rule fixable by replacing all matches with `replacement`,
+    /// which may refer to capture groups like `$1` or `${name}`.
+    pub fn replacement(mut self, replacement: Option<String>) -> Self {
+        self.replacement = replacement;
+        self
+    }
+}
+
+impl Rule for RegexRule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn description(&self) -> &str {
+        &self.message
+    }
+
+    fn default_severity(&self) -> Severity {
+        self.severity
+    }
+
+    fn applies_to(&self, path: &str) -> bool {
+        rule::matches_files(&self.files, path)
+    }
+
+    fn check_line(&self, line: &Line<'_>, _ws: &WsRule) -> bool {
+        self.pattern.is_match(line.text)
+    }
+
+    fn fixable(&self) -> bool {
+        self.replacement.is_some()
+    }
+
+    fn fix_line(&self, line: &mut FixedLine, _ws: &WsRule) {
+        let Some(ref replacement) = self.replacement else {
+            return;
+        };
+        let fixed = self
+            .pattern
+            .replace_all(line.text.as_bytes(), replacement.as_bytes());
+        // A replacement splitting a character is not applied.
+        if let Ok(fixed) = std::str::from_utf8(&fixed) {
+            line.text = fixed.to_string();
+        }
+    }
+}
@@ -0,0 +1,165 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Writing fixed files safely.
+//!
+//! Fixes are written to a temporary file in the directory of the file, which
+//! is synced to disk and renamed over the file, so that a crash leaves
+//! either the old or the fixed content. The mode and owner of the file are
+//! kept. Extended attributes and ACLs are not.
+
+use clap::ValueEnum;
+use log::warn;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::{fchown, MetadataExt};
+use std::path::{Path, PathBuf};
+
+/// How fixing treats symbolic links.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Symlinks {
+    /// Do not fix symbolic links, their issues are reported
+    #[default]
+    Skip,
+    /// Fix the file a link points to, if it is inside a linted directory or
+    /// the current directory
+    Follow,
+}
+
+/// How fixing treats files with more than one hard link.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HardLinks {
+    /// Do not fix them, their issues are reported
+    #[default]
+    Skip,
+    /// Replace the fixed name with a new file, the other names keep the old
+    /// content
+    Break,
+    /// Write the fixed content into the file itself, which all names share,
+    /// but not atomically
+    InPlace,
+}
+
+/// Decides which files are fixed, and how.
+#[derive(Debug)]
+pub struct Policy<'a> {
+    pub symlinks: Symlinks,
+    pub hard_links: HardLinks,
+    /// Canonical directories symbolic links may point into.
+    pub roots: &'a [PathBuf],
+}
+
+/// A file to write fixes to.
+#[derive(Debug)]
+pub struct Target {
+    pub path: PathBuf,
+    /// Whether to write into the file itself rather than replace it.
+    pub in_place: bool,
+}
+
+impl Policy<'_> {
+    /// Returns where fixes of `path` are written, or `None` with a warning if
+    /// the policy skips it.
+    pub fn target(&self, path: &Path) -> io::Result<Option<Target>> {
+        let mut target = path.to_path_buf();
+        if fs::symlink_metadata(path)?.file_type().is_symlink() {
+            if self.symlinks == Symlinks::Skip {
+                warn!(
+                    "not fixing symbolic link '{}', see --symlinks",
+                    path.display()
+                );
+                return Ok(None);
+            }
+            target = fs::canonicalize(path)?;
+            if !self.roots.iter().any(|root| target.starts_with(root)) {
+                warn!(
+                    "not fixing symbolic link '{}' to '{}' outside of the linted directories",
+                    path.display(),
+                    target.display()
+                );
+                return Ok(None);
+            }
+        }
+        let in_place = fs::metadata(&target)?.nlink() > 1 && {
+            if self.hard_links == HardLinks::Skip {
+                warn!(
+                    "not fixing '{}' with more than one hard link, see --hard-links",
+                    path.display()
+                );
+                return Ok(None);
+            }
+            self.hard_links == HardLinks::InPlace
+        };
+        Ok(Some(Target {
+            path: target,
+            in_place,
+        }))
+    }
+}
+
+/// Replaces the content of the file at `target` by what `fill` writes.
+///
+/// Fails without changing the file if it is not writable, or if its owner
+/// can not be kept.
+pub fn replace(
+    target: &Target,
+    fill: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+    let path = &target.path;
+    // Renaming does not need the file to be writable, fixing still should.
+    fs::OpenOptions::new().write(true).open(path)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{}.{}.clean-tmp", name, std::process::id()));
+    let result = write_tmp(path, &tmp, fill).and_then(|()| {
+        if target.in_place {
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .truncate(true)
+                .open(path)?;
+            io::copy(&mut fs::File::open(&tmp)?, &mut file)?;
+            file.sync_all()?;
+            fs::remove_file(&tmp)
+        } else {
+            fs::rename(&tmp, path)?;
+            sync_dir(path)
+        }
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Writes the temporary file `tmp` with the mode and owner of `path`, and
+/// syncs it to disk.
+fn write_tmp(
+    path: &Path,
+    tmp: &Path,
+    fill: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+    let md = fs::metadata(path)?;
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(tmp)?;
+    let mut out = io::BufWriter::new(file);
+    fill(&mut out)?;
+    let file = out.into_inner().map_err(|e| e.into_error())?;
+    let tmp_md = file.metadata()?;
+    if (tmp_md.uid(), tmp_md.gid()) != (md.uid(), md.gid()) {
+        fchown(&file, Some(md.uid()), Some(md.gid()))
+            .map_err(|e| io::Error::new(e.kind(), format!("failed to keep the owner: {}", e)))?;
+    }
+    // Changing the owner may clear set-user-ID bits, so the mode goes last.
+    file.set_permissions(md.permissions())?;
+    file.sync_all()
+}
+
+/// Syncs the directory of `path`, so that a rename in it is on disk.
+fn sync_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => fs::File::open(dir)?.sync_all(),
+        _ => fs::File::open(".")?.sync_all(),
+    }
+}
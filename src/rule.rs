@@ -0,0 +1,877 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! The [`Rule`] trait implemented by all checks, and the [`Registry`] that
+//! runs them.
+//!
+//! A registry lints content in a single pass, handing each line to the
+//! per-line hooks of all enabled rules and a summary of the file to their
+//! whole-file hooks afterwards. Fixing works the same way, so that both can
+//! be streamed, see [`crate::stream`].
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use crate::builtin;
+use crate::suppress::Suppressions;
+use crate::whitespace::WsRule;
+use crate::{should_ignore, Error, Issue, IssueType, Result};
+
+/// How serious the issues of a rule are.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    #[default]
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// A line handed to [`Rule::check_line`].
+#[derive(Debug, Clone, Copy)]
+pub struct Line<'a> {
+    /// 1-based line number.
+    pub number: usize,
+    /// The content of the line, without the line terminator and a CR before
+    /// it. Not validated as UTF-8.
+    pub text: &'a [u8],
+    /// Whether `text` was followed by a CR.
+    pub cr: bool,
+    /// Whether the line is terminated by a newline, which all lines but the
+    /// last one are.
+    pub newline: bool,
+}
+
+/// A summary of a file handed to [`Rule::check_file`] once all lines were
+/// checked.
+#[derive(Debug, Clone, Copy)]
+pub struct FileInfo<'a> {
+    pub path: &'a str,
+    /// The whole content, `None` if the file is streamed.
+    pub content: Option<&'a [u8]>,
+    /// The number of the last line, which is empty if the file ends with a
+    /// newline.
+    pub lines: usize,
+    pub ends_with_newline: bool,
+    /// The number of newlines at the end of the file, only separated by CRs.
+    pub trailing_newlines: usize,
+    /// Whether a line ends with a CRLF line terminator.
+    pub crlf: bool,
+    /// The numbers of the lines with a CR anywhere, in order.
+    pub cr_lines: &'a [usize],
+}
+
+/// Records the issues a rule finds in [`Rule::check_file`].
+pub struct Reporter<'a> {
+    path: &'a str,
+    entry: &'a Entry,
+    severity: Severity,
+    issues: &'a mut Vec<Issue>,
+    error: &'a mut Option<Error>,
+}
+
+impl Reporter<'_> {
+    /// Reports an issue of the rule at `line`.
+    pub fn report(&mut self, line: usize) {
+        self.issues
+            .push(self.entry.issue(self.path, line, self.severity));
+    }
+
+    /// Reports an issue of the rule at `line` with its own message instead
+    /// of the description of the rule.
+    pub fn report_message(&mut self, line: usize, message: impl Into<String>) {
+        let mut issue = self.entry.issue(self.path, line, self.severity);
+        issue.message = Some(message.into());
+        self.issues.push(issue);
+    }
+
+    /// Fails linting the file with [`Error::RuleFailed`].
+    pub fn fail(&mut self, message: impl Into<String>) {
+        self.error.get_or_insert_with(|| Error::RuleFailed {
+            id: self.entry.rule.id().to_string(),
+            message: message.into(),
+        });
+    }
+}
+
+/// A line being fixed by [`Rule::fix_line`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixedLine {
+    /// The content of the line, without the line terminator and a CR before
+    /// it.
+    pub text: String,
+    /// Whether `text` is followed by a CR.
+    pub cr: bool,
+    /// Whether the line is terminated by a newline.
+    pub newline: bool,
+}
+
+impl FixedLine {
+    fn new(line: &str, newline: bool) -> Self {
+        let (text, cr) = match line.strip_suffix('\r') {
+            Some(text) => (text, true),
+            None => (line, false),
+        };
+        FixedLine {
+            text: text.to_string(),
+            cr,
+            newline,
+        }
+    }
+
+    fn into_string(mut self) -> String {
+        if self.cr {
+            self.text.push('\r');
+        }
+        self.text
+    }
+}
+
+/// The end of a file being fixed by [`Rule::fix_end`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEnd {
+    /// The fixed lines from the last one that is not blank on, or all lines
+    /// if all of them are blank, without line terminators.
+    pub lines: Vec<String>,
+    /// Whether the file ends with a newline.
+    pub newline: bool,
+}
+
+/// An example of the issues of a rule, shown by `clean explain`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Example<'a> {
+    /// Text with issues of the rule.
+    pub bad: &'a str,
+    /// The same text without issues, as fixed if the rule is fixable.
+    pub good: &'a str,
+    /// The whitespace classes the rule is checked with in the example, as
+    /// for [`WsRule::parse`], empty for the defaults.
+    pub whitespace: &'a str,
+}
+
+/// A check of text files, with an optional fix.
+///
+/// All hooks do nothing by default, a rule implements those it needs.
+pub trait Rule: Send + Sync {
+    /// The id of the rule, used as the type of its issues, such as
+    /// `trailing_whitespace`.
+    fn id(&self) -> &str;
+
+    /// A short description of the rule, used as the message of its issues.
+    fn description(&self) -> &str;
+
+    /// A longer explanation of what the rule checks and why, empty if
+    /// there is none.
+    fn explanation(&self) -> &str {
+        ""
+    }
+
+    /// An example of the issues of the rule.
+    fn example(&self) -> Option<Example<'_>> {
+        None
+    }
+
+    /// The severity of issues of the rule, unless configured otherwise.
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    /// Whether the rule is checked and fixed with the whitespace rule `ws`.
+    fn enabled(&self, _ws: &WsRule) -> bool {
+        true
+    }
+
+    /// Whether the rule is checked and fixed in the file at `path`.
+    fn applies_to(&self, _path: &str) -> bool {
+        true
+    }
+
+    /// Returns whether `line` has an issue.
+    fn check_line(&self, _line: &Line<'_>, _ws: &WsRule) -> bool {
+        false
+    }
+
+    /// Whether [`Rule::check_line`] only looks at the start and the end of
+    /// a line, so that the middle of long lines may be skipped when
+    /// streaming.
+    fn line_ends_only(&self) -> bool {
+        false
+    }
+
+    /// Checks the file as a whole, after all of its lines were checked.
+    fn check_file(&self, _file: &FileInfo<'_>, _ws: &WsRule, _reporter: &mut Reporter<'_>) {}
+
+    /// Whether the rule can fix its issues.
+    fn fixable(&self) -> bool {
+        false
+    }
+
+    /// Fixes the content of the file at `path` as a whole, before its lines
+    /// are fixed. Returns `None` if there is nothing to fix.
+    ///
+    /// Not called for streamed files.
+    fn fix_content(&self, _path: &str, _content: &str, _ws: &WsRule) -> Option<String> {
+        None
+    }
+
+    /// Fixes the issue of a single line, if it has one.
+    fn fix_line(&self, _line: &mut FixedLine, _ws: &WsRule) {}
+
+    /// Fixes issues at the end of the file, after all lines were fixed.
+    fn fix_end(&self, _end: &mut FileEnd, _ws: &WsRule) {}
+}
+
+/// Checks that `id` is a valid id for a rule that is not built in.
+///
+/// Ids may only contain ASCII letters, digits, `_` and `-`.
+pub(crate) fn check_id(id: &str) -> Result<()> {
+    if id.is_empty()
+        || !id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+    {
+        return Err(Error::InvalidRule {
+            id: id.to_string(),
+            message: "ids may only contain ASCII letters, digits, '_' and '-'".into(),
+        });
+    }
+    Ok(())
+}
+
+/// Checks the glob patterns of files a rule applies to.
+pub(crate) fn check_files(id: &str, files: &[String]) -> Result<()> {
+    should_ignore("", files).map_err(|e| Error::InvalidRule {
+        id: id.to_string(),
+        message: format!("invalid glob pattern: {}", e),
+    })?;
+    Ok(())
+}
+
+/// Whether `path` matches one of the glob patterns `files`, like ignore
+/// patterns, or `files` is empty.
+pub(crate) fn matches_files(files: &[String], path: &str) -> bool {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    // Patterns were validated when the rule was created.
+    files.is_empty() || should_ignore(path, files).unwrap_or(false)
+}
+
+/// Severities of rules in the files matching some glob patterns, overriding
+/// the default severities of the rules.
+#[derive(Debug, Clone, Default)]
+pub struct Override {
+    files: Vec<String>,
+    severities: Vec<(String, Option<Severity>)>,
+}
+
+impl Override {
+    /// Returns an override for the files matching one of the glob patterns
+    /// `files`, like ignore patterns, or all files if `files` is empty.
+    pub fn new(files: Vec<String>) -> Result<Self> {
+        should_ignore("", &files)?;
+        Ok(Override {
+            files,
+            severities: Vec::new(),
+        })
+    }
+
+    /// Sets the severity of the rule `id`, `None` turning the rule off.
+    pub fn rule(mut self, id: &str, severity: Option<Severity>) -> Self {
+        self.severities.retain(|(r, _)| r != id);
+        self.severities.push((id.to_string(), severity));
+        self
+    }
+}
+
+/// A registered rule with the issue type of its id.
+#[derive(Clone)]
+struct Entry {
+    rule: Arc<dyn Rule>,
+    issue_type: IssueType,
+}
+
+impl Entry {
+    fn issue(&self, path: &str, line: usize, severity: Severity) -> Issue {
+        Issue {
+            issue_type: self.issue_type.clone(),
+            line: Some(line),
+            file: path.to_string(),
+            severity,
+            message: Some(self.rule.description().to_string()),
+        }
+    }
+}
+
+/// An ordered set of rules, which lints and fixes content.
+///
+/// The default registry has the built-in rules, in the order their issues
+/// are reported on a line.
+#[derive(Clone)]
+pub struct Registry {
+    entries: Vec<Entry>,
+    overrides: Vec<Override>,
+    root: Option<Root>,
+}
+
+/// The directory the glob patterns of files are relative to.
+#[derive(Clone, Debug)]
+struct Root {
+    dir: PathBuf,
+    /// The current directory, which relative paths are relative to.
+    cwd: PathBuf,
+}
+
+impl Root {
+    /// Returns `path` relative to the root, or as is if it is outside.
+    fn relative<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        let absolute = normalize(&self.cwd.join(path));
+        if let Ok(relative) = absolute.strip_prefix(&self.dir) {
+            return relative.to_string_lossy().into_owned().into();
+        }
+        // The path may lead into the root through a symbolic link.
+        match std::fs::canonicalize(&absolute) {
+            Ok(canonical) => match canonical.strip_prefix(&self.dir) {
+                Ok(relative) => relative.to_string_lossy().into_owned().into(),
+                Err(_) => path.into(),
+            },
+            Err(_) => path.into(),
+        }
+    }
+}
+
+/// Removes the `.` and `..` components of the absolute `path`.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Registry::new();
+        for rule in builtin::rules() {
+            registry.register(rule);
+        }
+        registry
+    }
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.entries.iter().map(|e| e.rule.id()))
+            .finish()
+    }
+}
+
+impl Registry {
+    /// Returns a registry without any rules.
+    pub fn new() -> Self {
+        Registry {
+            entries: Vec::new(),
+            overrides: Vec::new(),
+            root: None,
+        }
+    }
+
+    /// Matches the glob patterns of files of rules and overrides against
+    /// paths relative to the directory `dir`, rather than the paths as
+    /// given. Paths outside of it are matched as given.
+    ///
+    /// `dir` should be canonical, like the current directory.
+    pub fn root(&mut self, dir: &Path) {
+        self.root = Some(Root {
+            dir: dir.to_path_buf(),
+            cwd: std::env::current_dir().unwrap_or_default(),
+        });
+    }
+
+    /// Takes relative paths as relative to the directory `dir` rather than
+    /// the current directory, when matching them against a root.
+    pub fn relative_to(&mut self, dir: &Path) {
+        if let Some(root) = &mut self.root {
+            root.cwd = root.cwd.join(dir);
+        }
+    }
+
+    /// Returns `path` as matched against glob patterns of files.
+    fn matched_path<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        match &self.root {
+            Some(root) => root.relative(path),
+            None => path.into(),
+        }
+    }
+
+    /// Returns the shared registry of the built-in rules.
+    pub fn builtin() -> &'static Registry {
+        static BUILTIN: OnceLock<Registry> = OnceLock::new();
+        BUILTIN.get_or_init(Registry::default)
+    }
+
+    /// Adds `rule`, replacing a rule with the same id.
+    pub fn register(&mut self, rule: Arc<dyn Rule>) {
+        let entry = Entry {
+            issue_type: IssueType::from_id(rule.id()),
+            rule,
+        };
+        match self
+            .entries
+            .iter_mut()
+            .find(|e| e.rule.id() == entry.rule.id())
+        {
+            Some(e) => *e = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// Returns the rule with `id`.
+    pub fn get(&self, id: &str) -> Option<&dyn Rule> {
+        self.rules().find(|r| r.id() == id)
+    }
+
+    /// Returns all rules in order.
+    pub fn rules(&self) -> impl Iterator<Item = &dyn Rule> {
+        self.entries.iter().map(|e| &*e.rule)
+    }
+
+    /// Returns the rules that apply to `path`, in order.
+    pub fn rules_for<'a>(&'a self, path: &str) -> impl Iterator<Item = &'a dyn Rule> {
+        let path = self.matched_path(path).into_owned();
+        self.rules().filter(move |r| r.applies_to(&path))
+    }
+
+    /// Adds `o`, which takes precedence over the overrides added before.
+    pub fn add_override(&mut self, o: Override) {
+        self.overrides.push(o);
+    }
+
+    /// Returns the severity of issues of the rule `id` in the file at
+    /// `path`, or `None` if the rule is turned off there.
+    pub fn severity(&self, id: &str, path: &str) -> Option<Severity> {
+        self.severity_at(id, &self.matched_path(path))
+    }
+
+    /// Returns the severity of issues of the rule `id` at `path`, as
+    /// matched against glob patterns of files.
+    fn severity_at(&self, id: &str, path: &str) -> Option<Severity> {
+        let mut severity = Some(self.get(id)?.default_severity());
+        for o in &self.overrides {
+            if !matches_files(&o.files, path) {
+                continue;
+            }
+            if let Some((_, s)) = o.severities.iter().find(|(r, _)| r == id) {
+                severity = *s;
+            }
+        }
+        severity
+    }
+
+    /// Returns the rules checked and fixed in the file at `path`, with the
+    /// severity of their issues there.
+    fn enabled(&self, path: &str, ws: &WsRule) -> Vec<(&Entry, Severity)> {
+        let path = self.matched_path(path);
+        self.entries
+            .iter()
+            .filter(|e| e.rule.applies_to(&path) && e.rule.enabled(ws))
+            .filter_map(|e| Some((e, self.severity_at(e.rule.id(), &path)?)))
+            .collect()
+    }
+
+    pub(crate) fn checker<'a>(&'a self, path: &'a str, ws: &'a WsRule) -> Checker<'a> {
+        Checker {
+            path,
+            ws,
+            rules: self
+                .enabled(path, ws)
+                .into_iter()
+                .map(|(e, severity)| (e, severity, Vec::new()))
+                .collect(),
+            lines: 0,
+            // An empty file has no incomplete last line, and --fix keeps it
+            // empty.
+            ends_with_newline: true,
+            trailing_newlines: 0,
+            crlf: false,
+            cr_lines: Vec::new(),
+            suppressions: Suppressions::default(),
+        }
+    }
+
+    pub(crate) fn fixer<'a, W: Write>(
+        &'a self,
+        path: &'a str,
+        ws: &'a WsRule,
+        out: W,
+    ) -> Fixer<'a, W> {
+        Fixer {
+            ws,
+            rules: self
+                .enabled(path, ws)
+                .into_iter()
+                .map(|(e, _)| &*e.rule)
+                .collect(),
+            out,
+            written: 0,
+            tail: Vec::new(),
+            newline: false,
+            lines: 0,
+            suppressions: Suppressions::default(),
+            scanned: false,
+        }
+    }
+
+    /// Lints `content` in a single pass over its bytes.
+    ///
+    /// Issues are ordered by rule, and by line for each rule. Fails with
+    /// [`Error::RuleFailed`] if a rule cannot check the content.
+    pub fn lint(&self, path: &str, content: &[u8], ws: &WsRule) -> Result<Vec<Issue>> {
+        let mut checker = self.checker(path, ws);
+        let mut start = 0;
+        for pos in memchr::memchr_iter(b'\n', content) {
+            checker.line(&content[start..pos], true);
+            start = pos + 1;
+        }
+        checker.line(&content[start..], false);
+        checker.finish(Some(content))
+    }
+
+    /// Returns `content` of the file at `path` with the issues of all
+    /// fixable rules fixed.
+    ///
+    /// Lines where issues of a rule are suppressed are not fixed by it.
+    pub fn fix(&self, path: &str, content: &str, ws: &WsRule) -> String {
+        if content.is_empty() {
+            return String::new();
+        }
+        let mut fixed_content: Option<String> = None;
+        for (entry, _) in self.enabled(path, ws) {
+            let current = fixed_content.as_deref().unwrap_or(content);
+            if let Some(fixed) = entry.rule.fix_content(path, current, ws) {
+                let suppressions = Suppressions::scan(current.as_bytes());
+                fixed_content = Some(restore_suppressed(
+                    current,
+                    fixed,
+                    &suppressions,
+                    entry.rule.id(),
+                ));
+            }
+        }
+        let content = fixed_content.as_deref().unwrap_or(content);
+        let mut fixer = self.fixer(path, ws, Vec::with_capacity(content.len()));
+        fixer.suppressions = Suppressions::scan(content.as_bytes());
+        fixer.scanned = true;
+        let (body, newline) = match content.strip_suffix('\n') {
+            Some(body) => (body, true),
+            None => (content, false),
+        };
+        let mut lines = body.split('\n').peekable();
+        while let Some(line) = lines.next() {
+            let newline = newline || lines.peek().is_some();
+            fixer
+                .line(line, newline)
+                .expect("writing to a Vec cannot fail");
+        }
+        let fixed = fixer.finish().expect("writing to a Vec cannot fail");
+        String::from_utf8(fixed).expect("fixed content is valid UTF-8")
+    }
+
+    /// Fixes the issues of a single line of the file at `path`, without its
+    /// line terminator.
+    ///
+    /// A CR at the end is taken as part of a CRLF line terminator.
+    pub fn fix_line(&self, path: &str, line: &str, ws: &WsRule) -> String {
+        let mut fixed = FixedLine::new(line, true);
+        for (entry, _) in self.enabled(path, ws) {
+            entry.rule.fix_line(&mut fixed, ws);
+        }
+        fixed.into_string()
+    }
+}
+
+/// Returns `fixed`, the content fixed by `rule` as a whole, with the lines
+/// where its issues are suppressed restored from `content`.
+///
+/// Lines cannot be matched if their number was changed, the fix is taken as
+/// is then.
+fn restore_suppressed(
+    content: &str,
+    fixed: String,
+    suppressions: &Suppressions,
+    rule: &str,
+) -> String {
+    if suppressions.is_empty() {
+        return fixed;
+    }
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let fixed_lines: Vec<&str> = fixed.split_inclusive('\n').collect();
+    if lines.len() != fixed_lines.len() {
+        return fixed;
+    }
+    lines
+        .iter()
+        .zip(&fixed_lines)
+        .enumerate()
+        .map(|(i, (line, fixed_line))| {
+            if suppressions.is_suppressed(rule, i + 1) {
+                *line
+            } else {
+                *fixed_line
+            }
+        })
+        .collect()
+}
+
+/// Incremental state of linting a file, fed one line at a time.
+pub(crate) struct Checker<'a> {
+    path: &'a str,
+    ws: &'a WsRule,
+    /// The enabled rules with their severity and the issues they found so
+    /// far.
+    rules: Vec<(&'a Entry, Severity, Vec<Issue>)>,
+    lines: usize,
+    ends_with_newline: bool,
+    trailing_newlines: usize,
+    crlf: bool,
+    cr_lines: Vec<usize>,
+    suppressions: Suppressions,
+}
+
+impl Checker<'_> {
+    /// Whether all rules only look at the start and the end of lines.
+    pub(crate) fn line_ends_only(&self) -> bool {
+        self.rules.iter().all(|(e, _, _)| e.rule.line_ends_only())
+    }
+
+    /// Checks the next line, `line` without its newline.
+    ///
+    /// The content after the last newline must be passed too, even if it
+    /// is empty.
+    pub(crate) fn line(&mut self, line: &[u8], newline: bool) {
+        self.line_with_cr(line, newline, memchr::memchr(b'\r', line).is_some());
+    }
+
+    /// Checks the next line like [`Checker::line`], `has_cr` telling
+    /// whether it has a CR anywhere, which `line` may have lost if only part
+    /// of a long line is checked.
+    pub(crate) fn line_with_cr(&mut self, line: &[u8], newline: bool, has_cr: bool) {
+        self.lines += 1;
+        let (text, cr) = match line {
+            [text @ .., b'\r'] => (text, true),
+            _ => (line, false),
+        };
+        if has_cr {
+            self.cr_lines.push(self.lines);
+        }
+        self.crlf |= cr && newline;
+        self.suppressions.line(self.lines, text);
+        let line_info = Line {
+            number: self.lines,
+            text,
+            cr,
+            newline,
+        };
+        for (entry, severity, issues) in &mut self.rules {
+            if entry.rule.check_line(&line_info, self.ws) {
+                issues.push(entry.issue(self.path, self.lines, *severity));
+            }
+        }
+        if line.iter().any(|b| *b != b'\r') {
+            self.trailing_newlines = 0;
+        }
+        if newline {
+            self.trailing_newlines += 1;
+        }
+        self.ends_with_newline = newline || (line.is_empty() && self.ends_with_newline);
+    }
+
+    /// Runs the whole-file checks and returns all issues that are not
+    /// suppressed, and the unused suppressions.
+    pub(crate) fn finish(mut self, content: Option<&[u8]>) -> Result<Vec<Issue>> {
+        let file = FileInfo {
+            path: self.path,
+            content,
+            lines: self.lines,
+            ends_with_newline: self.ends_with_newline,
+            trailing_newlines: self.trailing_newlines,
+            crlf: self.crlf,
+            cr_lines: &self.cr_lines,
+        };
+        let mut error = None;
+        for (entry, severity, issues) in &mut self.rules {
+            let mut reporter = Reporter {
+                path: self.path,
+                entry,
+                severity: *severity,
+                issues,
+                error: &mut error,
+            };
+            entry.rule.check_file(&file, self.ws, &mut reporter);
+            if let Some(error) = error {
+                return Err(error);
+            }
+        }
+        if !self.suppressions.is_empty() {
+            self.suppress();
+        }
+        Ok(self
+            .rules
+            .into_iter()
+            .flat_map(|(_, _, issues)| issues)
+            .collect())
+    }
+
+    /// Removes suppressed issues, and reports the suppressions of enabled
+    /// rules that suppressed nothing.
+    fn suppress(&mut self) {
+        for (entry, _, issues) in &mut self.rules {
+            let id = entry.rule.id();
+            issues.retain(|i| {
+                !i.line
+                    .is_some_and(|line| self.suppressions.suppress(id, line))
+            });
+        }
+        let Some(index) = self
+            .rules
+            .iter()
+            .position(|(e, _, _)| e.issue_type == IssueType::UnusedSuppression)
+        else {
+            return;
+        };
+        let (entry, severity, _) = self.rules[index];
+        let mut unused = Vec::new();
+        for s in self.suppressions.iter().filter(|s| !s.used) {
+            // Rules that are not enabled here may be elsewhere.
+            let known = s
+                .rule
+                .as_ref()
+                .is_none_or(|r| self.rules.iter().any(|(e, _, _)| e.rule.id() == r));
+            if !known || self.suppressions.is_suppressed(entry.rule.id(), s.line) {
+                continue;
+            }
+            let mut issue = entry.issue(self.path, s.line, severity);
+            if let Some(ref rule) = s.rule {
+                issue.message = Some(format!("{} of {}", entry.rule.description(), rule));
+            }
+            unused.push(issue);
+        }
+        self.rules[index].2 = unused;
+    }
+}
+
+/// Incremental state of fixing a file, fed one line at a time.
+///
+/// Only the lines from the last one that is not blank on are held in
+/// memory, as [`Rule::fix_end`] may change them.
+pub(crate) struct Fixer<'a, W: Write> {
+    ws: &'a WsRule,
+    rules: Vec<&'a dyn Rule>,
+    out: W,
+    written: usize,
+    tail: Vec<String>,
+    newline: bool,
+    lines: usize,
+    suppressions: Suppressions,
+    /// Whether the suppressions of the whole file were collected beforehand,
+    /// otherwise they are collected one line at a time.
+    scanned: bool,
+}
+
+impl<W: Write> Fixer<'_, W> {
+    /// Writes `line` to the output, separated from the previous one by a
+    /// newline.
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.written > 0 {
+            self.out.write_all(b"\n")?;
+        }
+        self.written += 1;
+        self.out.write_all(line.as_bytes())
+    }
+
+    /// Returns the output the fixed lines are written to.
+    pub(crate) fn out_mut(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    /// Fixes the next line, `line` without its newline.
+    pub(crate) fn line(&mut self, line: &str, newline: bool) -> io::Result<()> {
+        self.lines += 1;
+        if !self.scanned {
+            self.suppressions.line(self.lines, line.as_bytes());
+        }
+        let mut fixed = FixedLine::new(line, newline);
+        for rule in &self.rules {
+            if !self.suppressions.is_empty()
+                && self.suppressions.is_suppressed(rule.id(), self.lines)
+            {
+                continue;
+            }
+            rule.fix_line(&mut fixed, self.ws);
+        }
+        let fixed = fixed.into_string();
+        if !fixed.trim_end().is_empty() {
+            for line in std::mem::take(&mut self.tail) {
+                self.write_line(&line)?;
+            }
+        }
+        self.tail.push(fixed);
+        self.newline = newline;
+        Ok(())
+    }
+
+    /// Runs the fixes of the end of the file and returns the output.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        let mut end = FileEnd {
+            lines: std::mem::take(&mut self.tail),
+            newline: self.newline,
+        };
+        // Issues at the end are reported on the empty line after the last
+        // newline, if there is one.
+        let last = self.lines + usize::from(self.newline);
+        for rule in &self.rules {
+            if self.suppressions.is_suppressed(rule.id(), last) {
+                continue;
+            }
+            rule.fix_end(&mut end, self.ws);
+        }
+        for line in &end.lines {
+            self.write_line(line)?;
+        }
+        if self.written > 0 && end.newline {
+            self.out.write_all(b"\n")?;
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
@@ -0,0 +1,292 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Streaming linting and fixing of files too large to be read at once.
+//!
+//! The results are the same as [`Registry::lint`] and [`Registry::fix`] on
+//! the whole content, except that whole-file checks do not get the content
+//! and whole-content fixes are not applied. Linting reads the content in
+//! chunks with bounded memory: if all rules only look at the start and the
+//! end of lines, like the built-in whitespace rules, lines longer than
+//! [`MAX_LINE`] are checked by their start and their end only. Otherwise,
+//! such as with regex rules, and when fixing, the current line is held in
+//! memory.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use crate::rule::Checker;
+use crate::whitespace::WsRule;
+use crate::{Error, Issue, Registry, Result};
+
+/// Files larger than this are streamed instead of read into memory.
+pub const STREAM_THRESHOLD: u64 = 8 * 1024 * 1024;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Lines longer than this are checked by their first bytes and their end,
+/// unless a rule needs whole lines.
+pub const MAX_LINE: usize = CHUNK_SIZE;
+
+/// How many bytes of the end of a long line are kept, enough for its last
+/// character and a CR.
+const LINE_END: usize = 8;
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "stream did not contain valid UTF-8",
+    )
+}
+
+/// Reads the next line into `buf`, removing its newline.
+///
+/// Returns whether the line was terminated by a newline, or `None` at the
+/// end of the content.
+fn read_line(reader: &mut impl BufRead, buf: &mut Vec<u8>) -> io::Result<Option<bool>> {
+    buf.clear();
+    if reader.read_until(b'\n', buf)? == 0 {
+        return Ok(None);
+    }
+    let newline = buf.last() == Some(&b'\n');
+    if newline {
+        buf.pop();
+    }
+    Ok(Some(newline))
+}
+
+/// Validates UTF-8 read in chunks, which may split characters.
+#[derive(Default)]
+struct Utf8 {
+    /// The start of a character split at the end of the last chunk.
+    partial: Vec<u8>,
+}
+
+impl Utf8 {
+    /// Returns whether `chunk` continues valid UTF-8.
+    fn feed(&mut self, mut chunk: &[u8]) -> bool {
+        if !self.partial.is_empty() {
+            let had = self.partial.len();
+            let take = chunk.len().min(4 - had);
+            self.partial.extend_from_slice(&chunk[..take]);
+            match std::str::from_utf8(&self.partial) {
+                Ok(_) => chunk = &chunk[take..],
+                Err(e) if e.valid_up_to() >= had => chunk = &chunk[e.valid_up_to() - had..],
+                // Still incomplete, the chunk was too short to complete it.
+                Err(e) if e.error_len().is_none() => return true,
+                Err(_) => return false,
+            }
+            self.partial.clear();
+        }
+        match std::str::from_utf8(chunk) {
+            Ok(_) => true,
+            Err(e) if e.error_len().is_none() => {
+                self.partial.extend_from_slice(&chunk[e.valid_up_to()..]);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Returns whether the content ended without a split character.
+    fn finish(&self) -> bool {
+        self.partial.is_empty()
+    }
+}
+
+/// The part of a line checked when streaming: its first `max` bytes, and
+/// the last [`LINE_END`] bytes after them.
+struct LinePart {
+    max: usize,
+    start: Vec<u8>,
+    end: Vec<u8>,
+    has_cr: bool,
+}
+
+impl LinePart {
+    fn new(max: usize) -> Self {
+        LinePart {
+            max,
+            start: Vec::new(),
+            end: Vec::new(),
+            has_cr: false,
+        }
+    }
+
+    fn push(&mut self, mut bytes: &[u8]) {
+        self.has_cr |= memchr::memchr(b'\r', bytes).is_some();
+        let room = self.max - self.start.len();
+        if room > 0 {
+            let n = room.min(bytes.len());
+            self.start.extend_from_slice(&bytes[..n]);
+            bytes = &bytes[n..];
+        }
+        self.end.extend_from_slice(bytes);
+        let excess = self.end.len().saturating_sub(LINE_END);
+        self.end.drain(..excess);
+    }
+
+    /// Checks the line and clears it for the next one.
+    fn check(&mut self, checker: &mut Checker<'_>, newline: bool) {
+        if !self.end.is_empty() {
+            // Both parts are cut at character boundaries, so that the line
+            // stays valid UTF-8.
+            if let Err(e) = std::str::from_utf8(&self.start) {
+                self.start.truncate(e.valid_up_to());
+            }
+            let first = self.end.iter().position(|b| b & 0xc0 != 0x80);
+            self.start
+                .extend_from_slice(&self.end[first.unwrap_or(self.end.len())..]);
+        }
+        checker.line_with_cr(&self.start, newline, self.has_cr);
+        self.start.clear();
+        self.end.clear();
+        self.has_cr = false;
+    }
+}
+
+/// Lints content read from `reader` in chunks, with bounded memory.
+///
+/// Fails with [`Error::NotUtf8`] if the content is not valid UTF-8, like
+/// [`std::fs::read_to_string`].
+pub fn lint_reader(
+    registry: &Registry,
+    path: &str,
+    reader: impl Read,
+    ws: &WsRule,
+) -> Result<Vec<Issue>> {
+    let mut reader = BufReader::with_capacity(CHUNK_SIZE, reader);
+    let mut checker = registry.checker(path, ws);
+    let mut utf8 = Utf8::default();
+    let mut line = LinePart::new(if checker.line_ends_only() {
+        MAX_LINE
+    } else {
+        usize::MAX
+    });
+    loop {
+        let chunk = reader.fill_buf()?;
+        if chunk.is_empty() {
+            break;
+        }
+        let (part, newline) = match memchr::memchr(b'\n', chunk) {
+            Some(pos) => (&chunk[..pos], true),
+            None => (chunk, false),
+        };
+        let consumed = part.len() + usize::from(newline);
+        if !utf8.feed(&chunk[..consumed]) {
+            return Err(Error::NotUtf8(path.into()));
+        }
+        line.push(part);
+        reader.consume(consumed);
+        if newline {
+            line.check(&mut checker, true);
+        }
+    }
+    if !utf8.finish() {
+        return Err(Error::NotUtf8(path.into()));
+    }
+    // The content after the last newline is a line too, maybe empty.
+    line.check(&mut checker, false);
+    checker.finish(None)
+}
+
+/// Passes the fixed content on to `out`, comparing it with the content it
+/// was fixed from, which is queued as it is read.
+struct Compare<W> {
+    out: W,
+    /// The content read that was not written yet, as long as both are the
+    /// same.
+    pending: VecDeque<u8>,
+    changed: bool,
+}
+
+impl<W> Compare<W> {
+    fn new(out: W) -> Self {
+        Compare {
+            out,
+            pending: VecDeque::new(),
+            changed: false,
+        }
+    }
+
+    /// Queues `bytes` read from the content.
+    fn read(&mut self, bytes: &[u8]) {
+        if !self.changed {
+            self.pending.extend(bytes);
+        }
+    }
+}
+
+impl<W: Write> Write for Compare<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.out.write(buf)?;
+        let written = &buf[..n];
+        if !self.changed
+            && (written.len() > self.pending.len()
+                || !self.pending.drain(..n).eq(written.iter().copied()))
+        {
+            self.changed = true;
+            self.pending = VecDeque::new();
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Writes the fixed content of `reader` to `out`, one line at a time.
+///
+/// Only the current line and trailing blank lines are held in memory.
+/// [`Rule::fix_content`](crate::Rule::fix_content) is not applied, and
+/// `clean:disable-file` only applies from the line of the directive on.
+///
+/// Returns whether the fixed content differs from the content read.
+pub fn fix_reader(
+    registry: &Registry,
+    path: &str,
+    reader: impl BufRead,
+    out: impl Write,
+    ws: &WsRule,
+) -> io::Result<bool> {
+    fix_lines(registry, path, reader, out, ws, false)
+}
+
+/// Returns whether [`fix_reader`] would change the content of `reader`,
+/// which is only read up to the first change.
+pub fn fix_changes(
+    registry: &Registry,
+    path: &str,
+    reader: impl BufRead,
+    ws: &WsRule,
+) -> io::Result<bool> {
+    fix_lines(registry, path, reader, io::sink(), ws, true)
+}
+
+fn fix_lines(
+    registry: &Registry,
+    path: &str,
+    mut reader: impl BufRead,
+    out: impl Write,
+    ws: &WsRule,
+    stop_on_change: bool,
+) -> io::Result<bool> {
+    let mut fixer = registry.fixer(path, ws, Compare::new(out));
+    let mut buf = Vec::new();
+    while let Some(newline) = read_line(&mut reader, &mut buf)? {
+        let line = std::str::from_utf8(&buf).map_err(|_| invalid_utf8())?;
+        let compare = fixer.out_mut();
+        compare.read(&buf);
+        if newline {
+            compare.read(b"\n");
+        }
+        if stop_on_change && compare.changed {
+            return Ok(true);
+        }
+        fixer.line(line, newline)?;
+    }
+    let compare = fixer.finish()?;
+    Ok(compare.changed || !compare.pending.is_empty())
+}
@@ -0,0 +1,114 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! The summary of what `--fix` changed, part of the report in every format.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use clean::Issue;
+
+/// A file changed by `--fix`, with how many issues of each rule were fixed.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FixedFile {
+    pub file: String,
+    pub rules: BTreeMap<String, usize>,
+}
+
+impl FixedFile {
+    /// Compares the issues of `file` before and after fixing it, each issue
+    /// that is gone counts as fixed by its rule.
+    pub fn new(file: &str, before: &[Issue], after: &[Issue]) -> Self {
+        let mut rules = counts(before);
+        for (id, count) in counts(after) {
+            if let Some(fixed) = rules.get_mut(&id) {
+                *fixed = fixed.saturating_sub(count);
+            }
+        }
+        rules.retain(|_, fixed| *fixed > 0);
+        FixedFile {
+            file: file.to_string(),
+            rules,
+        }
+    }
+}
+
+fn counts(issues: &[Issue]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for issue in issues {
+        *counts.entry(issue.issue_type.id().to_string()).or_default() += 1;
+    }
+    counts
+}
+
+/// The report of `--fix` in JSON and YAML.
+#[derive(serde::Serialize)]
+pub struct Report<'a> {
+    /// The files changed, sorted.
+    pub fixed: &'a [FixedFile],
+    /// How many issues of each rule were fixed in all files.
+    pub rules: BTreeMap<&'a str, usize>,
+    /// The issues left, which could not be fixed.
+    pub issues: &'a [Issue],
+}
+
+impl<'a> Report<'a> {
+    pub fn new(fixed: &'a [FixedFile], issues: &'a [Issue]) -> Self {
+        let mut rules = BTreeMap::new();
+        for (id, count) in fixed.iter().flat_map(|f| &f.rules) {
+            *rules.entry(id.as_str()).or_default() += count;
+        }
+        Report {
+            fixed,
+            rules,
+            issues,
+        }
+    }
+
+    /// Writes the fixed files of the human-readable report, with the total
+    /// of each rule.
+    pub fn write_fixed(&self, out: &mut dyn Write) -> io::Result<()> {
+        if self.fixed.is_empty() {
+            return Ok(());
+        }
+        writeln!(out, "## Fixed\n")?;
+        for file in self.fixed {
+            writeln!(out, "- `{}`: {}", file.file, list(&file.rules))?;
+        }
+        let total: usize = self.rules.values().sum();
+        writeln!(
+            out,
+            "\nFixed {} in {}: {}.\n",
+            plural(total, "issue"),
+            plural(self.fixed.len(), "file"),
+            list(&self.rules)
+        )
+    }
+
+    /// Writes the closing line of the human-readable report.
+    pub fn write_end(&self, out: &mut dyn Write) -> io::Result<()> {
+        match self.issues.len() {
+            0 if self.fixed.is_empty() => writeln!(out, "No lint issues found.\n"),
+            0 => writeln!(out, "No lint issues left.\n"),
+            n => writeln!(out, "{} could not be fixed.\n", plural(n, "issue")),
+        }
+    }
+}
+
+/// Formats counts of rules as "rule (count), ...".
+fn list<K: AsRef<str>>(rules: &BTreeMap<K, usize>) -> String {
+    rules
+        .iter()
+        .map(|(id, count)| format!("{} ({})", id.as_ref(), count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn plural(n: usize, noun: &str) -> String {
+    if n == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", n, noun)
+    }
+}
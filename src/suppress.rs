@@ -0,0 +1,227 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Inline suppression directives.
+//!
+//! Directives are written at the start of a comment, right after one of
+//! the [`COMMENT_MARKERS`] at the start of a line or after whitespace, and
+//! are followed by the ids of the rules they suppress, or suppress all rules
+//! if none are given:
+//!
+//! - `clean:ignore-next-line [RULE...]` suppresses issues of the next line.
+//! - `clean:disable [RULE...]` suppresses issues from its line on, until a
+//!   `clean:enable [RULE...]` line or the end of the file. An `enable`
+//!   without rules ends all ranges.
+//! - `clean:disable-file [RULE...]` suppresses issues of the whole file.
+//!
+//! Rule ids are separated by spaces or commas. Anything else after them,
+//! such as the end of a comment, is ignored. Directives elsewhere, such as
+//! in strings or quoted in prose, are not directives.
+
+use memchr::memmem;
+
+const PREFIX: &[u8] = b"clean:";
+
+/// The comment markers directives follow, with spaces in between.
+const COMMENT_MARKERS: &[&[u8]] = &[
+    b"#", b"##", b"//", b"///", b"//!", b"/*", b"/**", b"/*!", b"*", b"<!--", b"--", b";", b";;",
+    b"%", b"{-", b"(*", b"..",
+];
+
+/// A line that a directive suppresses to the end of the file, until it is
+/// ended.
+const OPEN: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    IgnoreNextLine,
+    Disable,
+    Enable,
+    DisableFile,
+}
+
+/// A range of lines in which issues of a rule, or of all rules, are
+/// suppressed.
+#[derive(Debug, Clone)]
+pub(crate) struct Suppression {
+    /// The line of the directive.
+    pub(crate) line: usize,
+    /// The suppressed rule, `None` for all rules.
+    pub(crate) rule: Option<String>,
+    first: usize,
+    last: usize,
+    /// Whether it comes from `disable-file`, which cannot be ended.
+    file: bool,
+    /// Whether an issue was suppressed.
+    pub(crate) used: bool,
+}
+
+impl Suppression {
+    fn covers(&self, rule: &str, line: usize) -> bool {
+        (self.first..=self.last).contains(&line) && self.rule.as_deref().is_none_or(|r| r == rule)
+    }
+}
+
+/// The suppressions of a file, collected one line at a time.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Suppressions {
+    list: Vec<Suppression>,
+}
+
+impl Suppressions {
+    /// Collects the suppressions of all lines of `content`.
+    pub(crate) fn scan(content: &[u8]) -> Self {
+        let mut suppressions = Suppressions::default();
+        // Lines without the prefix are skipped without being split.
+        let (mut counted, mut number) = (0, 1);
+        let mut end = 0;
+        for pos in memmem::find_iter(content, PREFIX) {
+            // A line with several directives is found more than once.
+            if pos < end {
+                continue;
+            }
+            let start = memchr::memrchr(b'\n', &content[..pos]).map_or(0, |i| i + 1);
+            end = memchr::memchr(b'\n', &content[pos..]).map_or(content.len(), |i| pos + i);
+            number += memchr::memchr_iter(b'\n', &content[counted..start]).count();
+            counted = start;
+            suppressions.line(number, &content[start..end]);
+        }
+        suppressions
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Collects the directives of line `number`, which is `text` without its
+    /// line terminator.
+    pub(crate) fn line(&mut self, number: usize, text: &[u8]) {
+        let mut rest = text;
+        while let Some(pos) = memmem::find(rest, PREFIX) {
+            let in_comment = after_comment_marker(&rest[..pos]);
+            rest = &rest[pos + PREFIX.len()..];
+            if !in_comment {
+                continue;
+            }
+            let Some((kind, rules)) = parse(rest) else {
+                continue;
+            };
+            self.directive(number, kind, rules);
+        }
+    }
+
+    fn directive(&mut self, number: usize, kind: Kind, rules: Vec<String>) {
+        let rules: Vec<Option<String>> = if rules.is_empty() {
+            vec![None]
+        } else {
+            rules.into_iter().map(Some).collect()
+        };
+        let (first, last) = match kind {
+            Kind::IgnoreNextLine => (number + 1, number + 1),
+            Kind::Disable => (number, OPEN),
+            Kind::DisableFile => (1, OPEN),
+            Kind::Enable => {
+                for s in &mut self.list {
+                    if s.last == OPEN && !s.file && (rules == [None] || rules.contains(&s.rule)) {
+                        s.last = number;
+                    }
+                }
+                return;
+            }
+        };
+        for rule in rules {
+            self.list.push(Suppression {
+                line: number,
+                rule,
+                first,
+                last,
+                file: kind == Kind::DisableFile,
+                used: false,
+            });
+        }
+    }
+
+    /// Returns whether issues of `rule` at `line` are suppressed, marking the
+    /// suppressions covering it as used.
+    pub(crate) fn suppress(&mut self, rule: &str, line: usize) -> bool {
+        let mut suppressed = false;
+        for s in &mut self.list {
+            if s.covers(rule, line) {
+                s.used = true;
+                suppressed = true;
+            }
+        }
+        suppressed
+    }
+
+    /// Returns whether issues of `rule` at `line` are suppressed.
+    pub(crate) fn is_suppressed(&self, rule: &str, line: usize) -> bool {
+        self.list.iter().any(|s| s.covers(rule, line))
+    }
+
+    /// Returns all suppressions, in the order of their directives.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Suppression> {
+        self.list.iter()
+    }
+}
+
+/// Whether `before`, the text before a directive, ends with a comment
+/// marker that is a word of its own, maybe followed by spaces.
+fn after_comment_marker(before: &[u8]) -> bool {
+    let end = before
+        .iter()
+        .rposition(|b| !matches!(b, b' ' | b'\t'))
+        .map_or(0, |i| i + 1);
+    let before = &before[..end];
+    let start = before
+        .iter()
+        .rposition(u8::is_ascii_whitespace)
+        .map_or(0, |i| i + 1);
+    COMMENT_MARKERS.contains(&&before[start..])
+}
+
+fn is_id_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'-'
+}
+
+/// Parses a directive and its rule ids, `text` being what follows the
+/// prefix.
+fn parse(text: &[u8]) -> Option<(Kind, Vec<String>)> {
+    let len = text.iter().take_while(|b| is_id_byte(**b)).count();
+    let kind = match &text[..len] {
+        b"ignore-next-line" => Kind::IgnoreNextLine,
+        b"disable" => Kind::Disable,
+        b"enable" => Kind::Enable,
+        b"disable-file" => Kind::DisableFile,
+        _ => return None,
+    };
+    let mut rest = &text[len..];
+    // Directives are words of their own, not `clean:disable.` in prose.
+    if !matches!(rest.first(), None | Some(b' ' | b'\t' | b',' | b'\r')) {
+        return None;
+    }
+    let mut rules = Vec::new();
+    loop {
+        let skip = rest
+            .iter()
+            .take_while(|b| matches!(b, b' ' | b'\t' | b','))
+            .count();
+        rest = &rest[skip..];
+        if !rest.first().is_some_and(u8::is_ascii_alphanumeric) {
+            break;
+        }
+        let mut len = rest.iter().take_while(|b| is_id_byte(**b)).count();
+        // The end of an HTML comment is not part of the id.
+        while rest[len - 1] == b'-' {
+            len -= 1;
+        }
+        // Ids are ASCII, so this is valid UTF-8.
+        rules.push(String::from_utf8_lossy(&rest[..len]).into_owned());
+        rest = &rest[len..];
+        if !matches!(rest.first(), Some(b' ' | b'\t' | b',')) {
+            break;
+        }
+    }
+    Some((kind, rules))
+}
@@ -0,0 +1,194 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Watch mode: lint files again as they change.
+
+use anyhow::Result;
+use log::{info, warn};
+use notify::event::{AccessKind, AccessMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::cache::Cache;
+use clean::whitespace::{RuleResolvers, WsRule};
+use clean::Registry;
+use crate::{ignored, lint_files, lint_one, open_output, write_report, Cli, DirFilter, Issue};
+
+/// Changes arriving within this time of each other are handled together, as
+/// editors often write a file in several steps.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// A path given on the command line, with its canonical form, which is how
+/// the watcher may report paths below it.
+struct Root {
+    path: PathBuf,
+    canonical: PathBuf,
+}
+
+impl Root {
+    fn new(path: &Path) -> Self {
+        Root {
+            path: path.to_path_buf(),
+            canonical: path.canonicalize().unwrap_or_else(|_| path.to_path_buf()),
+        }
+    }
+
+    /// Maps a path reported by the watcher back to the form used in reports.
+    fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        if let Ok(rel) = path.strip_prefix(&self.canonical) {
+            return Some(self.path.join(rel));
+        }
+        path.strip_prefix(&self.path)
+            .ok()
+            .map(|_| path.to_path_buf())
+    }
+}
+
+/// Whether `event` may have changed the content of a file.
+fn is_change(event: &Event) -> bool {
+    matches!(
+        event.kind,
+        EventKind::Create(_)
+            | EventKind::Modify(_)
+            | EventKind::Remove(_)
+            | EventKind::Access(AccessKind::Close(AccessMode::Write))
+    )
+}
+
+/// Prints the report of all current issues, on a cleared screen if the
+/// output is a terminal.
+fn print(cli: &Cli, results: &BTreeMap<String, Vec<Issue>>) -> Result<()> {
+    let mut out = open_output(cli)?;
+    if cli.output.is_none() && std::io::stdout().is_terminal() {
+        write!(out, "\x1b[2J\x1b[H")?;
+    }
+    write_report(
+        cli,
+        &mut out,
+        results.values().flatten().cloned().collect(),
+        &[],
+    )
+}
+
+/// Lints `jobs`, then watches `files` and `dirs` and lints changed files
+/// again until interrupted.
+pub fn run(
+    cli: &Cli,
+    registry: &Registry,
+    cache: Option<&Cache>,
+    files: &[PathBuf],
+    dirs: &[PathBuf],
+    jobs: Vec<(PathBuf, WsRule)>,
+) -> Result<()> {
+    let mut results: BTreeMap<String, Vec<Issue>> = BTreeMap::new();
+    for issue in lint_files(cli, registry, cache, &jobs)?.issues {
+        results.entry(issue.file.clone()).or_default().push(issue);
+    }
+    print(cli, &results)?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    let dir_roots: Vec<Root> = dirs.iter().map(|d| Root::new(d)).collect();
+    for root in &dir_roots {
+        watcher.watch(&root.path, RecursiveMode::Recursive)?;
+    }
+    // Files are often replaced rather than written to, so their parent
+    // directories are watched instead.
+    let file_roots: Vec<Root> = files.iter().map(|f| Root::new(f)).collect();
+    let mut parents = BTreeSet::new();
+    for root in &file_roots {
+        if let Some(parent) = root.canonical.parent() {
+            parents.insert(parent.to_path_buf());
+        }
+    }
+    for parent in &parents {
+        watcher.watch(parent, RecursiveMode::NonRecursive)?;
+    }
+    info!("watching for changes, press Ctrl-C to stop");
+
+    loop {
+        // Wait for a change, then until no more changes arrive for a while.
+        let mut changed = BTreeSet::new();
+        let mut deadline: Option<Instant> = None;
+        loop {
+            let event = match deadline {
+                None => rx.recv()?,
+                Some(deadline) => {
+                    match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                        Ok(event) => event,
+                        Err(mpsc::RecvTimeoutError::Timeout) => break,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            };
+            match event {
+                Ok(e) if is_change(&e) => {
+                    changed.extend(e.paths);
+                    deadline = Some(Instant::now() + DEBOUNCE);
+                }
+                Ok(_) => {}
+                Err(e) => warn!("failed to watch files: {}", e),
+            }
+        }
+
+        // Filters are created for each batch, so that newly tracked files
+        // and changed gitattributes are taken into account.
+        let mut filters: HashMap<usize, DirFilter> = HashMap::new();
+        let mut resolvers = RuleResolvers::default();
+        let mut updated = false;
+        for changed_path in changed {
+            let mut rule = None;
+            let mut path = None;
+            if let Some(p) = file_roots.iter().find_map(|r| {
+                (r.canonical == changed_path || r.path == changed_path).then(|| r.path.clone())
+            }) {
+                if p.is_file() && !ignored(cli, &p.to_string_lossy()) {
+                    rule = resolvers.rule_for(&p)?;
+                }
+                path = Some(p);
+            } else if let Some((i, p)) = dir_roots
+                .iter()
+                .enumerate()
+                .find_map(|(i, r)| r.resolve(&changed_path).map(|p| (i, p)))
+            {
+                if p.is_file() {
+                    let filter = match filters.entry(i) {
+                        Entry::Occupied(e) => e.into_mut(),
+                        Entry::Vacant(e) => e.insert(DirFilter::new(cli, &dir_roots[i].path)?),
+                    };
+                    rule = filter.rule_for(cli, &p)?;
+                }
+                path = Some(p);
+            }
+            let Some(path) = path else {
+                continue;
+            };
+            let key = path.to_string_lossy().to_string();
+            let issues = match rule {
+                Some(ws) => match lint_one(cli, registry, cache, &path, &ws) {
+                    Ok(linted) => linted.issues,
+                    Err(e) => {
+                        warn!("{}", e);
+                        continue;
+                    }
+                },
+                None => Vec::new(),
+            };
+            let previous = if issues.is_empty() {
+                results.remove(&key)
+            } else {
+                results.insert(key, issues.clone())
+            };
+            updated |= previous.unwrap_or_default() != issues;
+        }
+        if updated {
+            print(cli, &results)?;
+        }
+    }
+}
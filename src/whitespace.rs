@@ -0,0 +1,388 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Whitespace rules compatible with git's `core.whitespace` and the
+//! `whitespace` attribute, see git-config(1) and gitattributes(5).
+
+use log::{debug, warn};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::git::{self, AttrValue, GitAttributes};
+use crate::{Error, Result};
+
+const DEFAULT_TAB_WIDTH: usize = 8;
+
+/// Which whitespace errors are checked and fixed for a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WsRule {
+    /// `blank-at-eol`: trailing whitespace at the end of a line.
+    pub blank_at_eol: bool,
+    /// `blank-at-eof`: blank lines at the end of the file.
+    pub blank_at_eof: bool,
+    /// `space-before-tab`: a space before a tab in the indent.
+    pub space_before_tab: bool,
+    /// `indent-with-non-tab`: a line indented with `tab_width` or more spaces.
+    pub indent_with_non_tab: bool,
+    /// `tab-in-indent`: a tab in the indent.
+    pub tab_in_indent: bool,
+    /// `incomplete-line`: missing newline at the end of the file.
+    pub missing_newline: bool,
+    /// CRLF line endings, disabled by git's `cr-at-eol`.
+    pub crlf: bool,
+    /// `tabwidth=<n>`: how many columns a tab occupies.
+    pub tab_width: usize,
+}
+
+impl Default for WsRule {
+    /// The same classes git checks when `core.whitespace` is not set,
+    /// plus missing newline and CRLF checks.
+    fn default() -> Self {
+        WsRule {
+            blank_at_eol: true,
+            blank_at_eof: true,
+            space_before_tab: true,
+            indent_with_non_tab: false,
+            tab_in_indent: false,
+            missing_newline: true,
+            crlf: true,
+            tab_width: DEFAULT_TAB_WIDTH,
+        }
+    }
+}
+
+impl WsRule {
+    /// No whitespace errors at all, as for `-whitespace`.
+    pub fn none() -> Self {
+        WsRule {
+            blank_at_eol: false,
+            blank_at_eof: false,
+            space_before_tab: false,
+            indent_with_non_tab: false,
+            tab_in_indent: false,
+            missing_newline: false,
+            crlf: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+        }
+    }
+
+    /// All whitespace errors except `tab-in-indent`, as for `whitespace`
+    /// being set.
+    pub fn all(tab_width: usize) -> Self {
+        WsRule {
+            indent_with_non_tab: true,
+            tab_width,
+            ..WsRule::default()
+        }
+    }
+
+    /// Parses a comma separated list of whitespace classes on top of the
+    /// defaults, such as `trailing-space,-space-before-tab,tabwidth=4`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut rule = WsRule::default();
+        for token in spec.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let (negated, name) = match token.strip_prefix('-') {
+                Some(name) => (true, name),
+                None => (false, token),
+            };
+            if let Some(width) = name.strip_prefix("tabwidth=") {
+                match width.parse::<usize>() {
+                    Ok(w) if (1..64).contains(&w) => rule.tab_width = w,
+                    _ => {
+                        return Err(Error::WhitespaceRule(format!(
+                            "tabwidth {:?} out of range",
+                            width
+                        )))
+                    }
+                }
+                continue;
+            }
+            let on = !negated;
+            match name {
+                "trailing-space" => {
+                    rule.blank_at_eol = on;
+                    rule.blank_at_eof = on;
+                }
+                "blank-at-eol" => rule.blank_at_eol = on,
+                "blank-at-eof" => rule.blank_at_eof = on,
+                "space-before-tab" => rule.space_before_tab = on,
+                "indent-with-non-tab" => rule.indent_with_non_tab = on,
+                "tab-in-indent" => rule.tab_in_indent = on,
+                "incomplete-line" => rule.missing_newline = on,
+                "cr-at-eol" => rule.crlf = !on,
+                _ => warn!("unknown whitespace class '{}' ignored", name),
+            }
+        }
+        if rule.tab_in_indent && rule.indent_with_non_tab {
+            return Err(Error::WhitespaceRule(
+                "cannot enforce both tab-in-indent and indent-with-non-tab".into(),
+            ));
+        }
+        Ok(rule)
+    }
+
+    /// Applies the `whitespace` attribute of a path on top of `self`, which
+    /// comes from `core.whitespace`.
+    pub fn with_attr(&self, attr: &AttrValue) -> Result<Self> {
+        match attr {
+            AttrValue::Set => Ok(WsRule::all(self.tab_width)),
+            AttrValue::Unset => Ok(WsRule::none()),
+            AttrValue::Unspecified => Ok(*self),
+            AttrValue::Value(spec) => WsRule::parse(spec),
+        }
+    }
+}
+
+/// Problems found in the indent of a single line.
+#[derive(Debug, Default)]
+pub struct IndentErrors {
+    pub space_before_tab: bool,
+    pub indent_with_non_tab: bool,
+    pub tab_in_indent: bool,
+}
+
+impl IndentErrors {
+    pub fn any(&self) -> bool {
+        self.space_before_tab || self.indent_with_non_tab || self.tab_in_indent
+    }
+}
+
+fn split_indent(line: &str) -> (&str, &str) {
+    let rest = line.trim_start_matches([' ', '\t']);
+    line.split_at(line.len() - rest.len())
+}
+
+/// Checks the leading whitespace of `line` the same way `git diff --check` does.
+pub fn check_indent(line: &str, rule: &WsRule) -> IndentErrors {
+    let (indent, _) = split_indent(line);
+    let mut errors = IndentErrors::default();
+    if rule.space_before_tab {
+        errors.space_before_tab = indent.contains(" \t");
+    }
+    if rule.indent_with_non_tab {
+        let after_tab = indent.rsplit('\t').next().unwrap_or_default();
+        errors.indent_with_non_tab = after_tab.len() >= rule.tab_width;
+    }
+    if rule.tab_in_indent {
+        errors.tab_in_indent = indent.contains('\t');
+    }
+    errors
+}
+
+/// Like [`check_indent`], in a single pass over the bytes of `line`.
+pub fn check_indent_bytes(line: &[u8], rule: &WsRule) -> IndentErrors {
+    let mut has_tab = false;
+    let mut space_before_tab = false;
+    let mut spaces_since_tab = 0;
+    for &b in line {
+        match b {
+            b' ' => spaces_since_tab += 1,
+            b'\t' => {
+                has_tab = true;
+                space_before_tab |= spaces_since_tab > 0;
+                spaces_since_tab = 0;
+            }
+            _ => break,
+        }
+    }
+    IndentErrors {
+        space_before_tab: rule.space_before_tab && space_before_tab,
+        indent_with_non_tab: rule.indent_with_non_tab && spaces_since_tab >= rule.tab_width,
+        tab_in_indent: rule.tab_in_indent && has_tab,
+    }
+}
+
+/// Rewrites the indent of `line` if it has any enabled indent error.
+pub fn fix_indent(line: &str, rule: &WsRule) -> String {
+    if !check_indent(line, rule).any() {
+        return line.to_string();
+    }
+    let (indent, rest) = split_indent(line);
+    let mut width = 0;
+    for c in indent.chars() {
+        if c == '\t' {
+            width = (width / rule.tab_width + 1) * rule.tab_width;
+        } else {
+            width += 1;
+        }
+    }
+    let indent = if rule.tab_in_indent {
+        " ".repeat(width)
+    } else {
+        "\t".repeat(width / rule.tab_width) + &" ".repeat(width % rule.tab_width)
+    };
+    indent + rest
+}
+
+/// The gitattributes that affect how a file is linted.
+const ATTRIBUTES: &[&str] = &["whitespace", "text", "binary", "eol", "clean"];
+
+/// Resolves the effective [`WsRule`] for paths below a directory from
+/// `core.whitespace` and gitattributes:
+///
+/// - `binary` and `-text` files are skipped,
+/// - `eol=crlf` files accept CRLF line endings,
+/// - `whitespace` selects the whitespace classes like `core.whitespace`,
+/// - `-clean` skips a file, `clean` lints it even if it is binary.
+///
+/// An invalid `core.whitespace` is replaced by the default rule, and an
+/// invalid `whitespace` attribute by `core.whitespace`, with a warning.
+pub struct RuleResolver {
+    core: WsRule,
+    attrs: Option<GitAttributes>,
+    /// The invalid `whitespace` attributes warned about.
+    invalid: HashSet<String>,
+}
+
+impl RuleResolver {
+    pub fn new(dir: &Path) -> Result<Self> {
+        let core = match git::config_get(dir, "core.whitespace") {
+            Ok(Some(spec)) => WsRule::parse(&spec).unwrap_or_else(|e| {
+                warn!("invalid core.whitespace, using the default: {}", e);
+                WsRule::default()
+            }),
+            Ok(None) => WsRule::default(),
+            Err(e) => {
+                debug!("failed to read core.whitespace: {}", e);
+                WsRule::default()
+            }
+        };
+        let attrs = match GitAttributes::spawn(dir, ATTRIBUTES) {
+            Ok(attrs) => attrs,
+            Err(e) => {
+                debug!("failed to run `git check-attr`: {}", e);
+                None
+            }
+        };
+        Ok(RuleResolver {
+            core,
+            attrs,
+            invalid: HashSet::new(),
+        })
+    }
+
+    /// Returns the rule of `core.whitespace`, which applies to paths without
+    /// attributes.
+    pub fn core(&self) -> WsRule {
+        self.core
+    }
+
+    /// Returns the rule for `path`, relative to the directory of the resolver,
+    /// or `None` if the file should not be linted at all.
+    pub fn rule_for(&mut self, path: &str) -> Result<Option<WsRule>> {
+        let Some(ref mut attrs) = self.attrs else {
+            return Ok(Some(self.core));
+        };
+        let values = attrs.get(path)?;
+        Ok(self.rule_of(&values))
+    }
+
+    /// Like [`RuleResolver::rule_for`] for all `paths`, looking up their
+    /// attributes at once.
+    pub fn rules_for(&mut self, paths: &[&str]) -> Result<Vec<Option<WsRule>>> {
+        let Some(ref mut attrs) = self.attrs else {
+            return Ok(vec![Some(self.core); paths.len()]);
+        };
+        let values = attrs.get_all(paths)?;
+        Ok(values.iter().map(|v| self.rule_of(v)).collect())
+    }
+
+    /// Returns the rule for a file with the attribute `values`.
+    fn rule_of(&mut self, values: &HashMap<&str, AttrValue>) -> Option<WsRule> {
+        let get = |name| values.get(name).unwrap_or(&AttrValue::Unspecified);
+        match get("clean") {
+            AttrValue::Unset => return None,
+            AttrValue::Set => {}
+            _ => {
+                if *get("binary") == AttrValue::Set || *get("text") == AttrValue::Unset {
+                    return None;
+                }
+            }
+        }
+        let whitespace = get("whitespace");
+        let mut rule = self.core.with_attr(whitespace).unwrap_or_else(|e| {
+            if let AttrValue::Value(spec) = whitespace {
+                if self.invalid.insert(spec.clone()) {
+                    warn!(
+                        "invalid whitespace attribute '{}', using core.whitespace: {}",
+                        spec, e
+                    );
+                }
+            }
+            self.core
+        });
+        if *get("eol") == AttrValue::Value("crlf".into()) {
+            rule.crlf = false;
+        }
+        Some(rule)
+    }
+}
+
+/// Caches one [`RuleResolver`] per git work tree, for explicitly given files.
+#[derive(Default)]
+pub struct RuleResolvers {
+    /// The top-level directories of the work trees of canonical
+    /// directories, `None` outside of a work tree.
+    tops: HashMap<PathBuf, Option<PathBuf>>,
+    /// The resolvers by work tree, the one for files outside of a work tree
+    /// only applies `core.whitespace`.
+    resolvers: HashMap<Option<PathBuf>, RuleResolver>,
+}
+
+impl RuleResolvers {
+    pub fn rule_for(&mut self, path: &Path) -> Result<Option<WsRule>> {
+        Ok(self.rules_for(std::slice::from_ref(&path))?.remove(0))
+    }
+
+    /// Returns the rules for all `paths`, looking up the attributes of the
+    /// files of each work tree at once.
+    pub fn rules_for(&mut self, paths: &[impl AsRef<Path>]) -> Result<Vec<Option<WsRule>>> {
+        let mut groups: HashMap<Option<PathBuf>, Vec<(usize, String)>> = HashMap::new();
+        for (i, path) in paths.iter().enumerate() {
+            let (top, path) = self.locate(path.as_ref())?;
+            groups.entry(top).or_default().push((i, path));
+        }
+        let mut rules = vec![None; paths.len()];
+        for (top, files) in groups {
+            let resolver = match self.resolvers.entry(top) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => {
+                    let dir = e.key().clone().unwrap_or_else(|| PathBuf::from("/"));
+                    e.insert(RuleResolver::new(&dir)?)
+                }
+            };
+            let names: Vec<&str> = files.iter().map(|(_, p)| p.as_str()).collect();
+            for ((i, _), rule) in files.iter().zip(resolver.rules_for(&names)?) {
+                rules[*i] = rule;
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Returns the work tree of `path`, and the path relative to it.
+    fn locate(&mut self, path: &Path) -> Result<(Option<PathBuf>, String)> {
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        let dir = dir.canonicalize()?;
+        let name = path.file_name().unwrap_or_default();
+        // Looking for the `.git` entry is much cheaper than asking git.
+        let top = self
+            .tops
+            .entry(dir.clone())
+            .or_insert_with(|| {
+                dir.ancestors()
+                    .find(|d| d.join(".git").exists())
+                    .map(Path::to_path_buf)
+            })
+            .clone();
+        let path = match &top {
+            Some(top) => dir.strip_prefix(top).unwrap_or(&dir).join(name),
+            None => dir.join(name),
+        };
+        Ok((top, path.to_string_lossy().into_owned()))
+    }
+}
@@ -4,7 +4,32 @@
 
 use assert_cmd::Command;
 use std::fs;
-use tempfile;
+use std::path::PathBuf;
+
+thread_local! {
+    /// The XDG state and cache directories of the current test, removed when
+    /// it ends, so that tests never touch the undo journal and cache of the
+    /// user.
+    static XDG_HOME: tempfile::TempDir = tempfile::tempdir().unwrap();
+}
+
+/// Returns the environment pointing the XDG state and cache directories into
+/// the tempdir of the current test.
+fn xdg_env() -> [(&'static str, PathBuf); 2] {
+    XDG_HOME.with(|home| {
+        [
+            ("XDG_STATE_HOME", home.path().join("state")),
+            ("XDG_CACHE_HOME", home.path().join("cache")),
+        ]
+    })
+}
+
+/// Returns a command running clean in the environment of [`xdg_env`].
+fn clean() -> Command {
+    let mut cmd = Command::cargo_bin("clean").unwrap();
+    cmd.envs(xdg_env());
+    cmd
+}
 
 // Test: should fail if trailing whitespace is found
 #[test]
@@ -13,13 +38,14 @@ fn test_lint_trailing_whitespace() {
     let temp = tempfile::tempdir().unwrap();
     let file_path = temp.path().join("test.txt");
     fs::write(&file_path, "hello \nworld\n").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--json");
     let assert = cmd.assert().failure();
     let output = String::from_utf8_lossy(&assert.get_output().stdout);
     let json: serde_json::Value = serde_json::from_str(&output).unwrap();
     let expected = serde_json::json!([{
         "type": "trailing_whitespace",
+        "severity": "error",
         "line": 1,
         "file": file_path.to_string_lossy().to_string(),
     }]);
@@ -33,13 +59,14 @@ fn test_lint_missing_newline() {
     let temp = tempfile::tempdir().unwrap();
     let file_path = temp.path().join("test2.txt");
     fs::write(&file_path, "hello").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--json");
     let assert = cmd.assert().failure();
     let output = String::from_utf8_lossy(&assert.get_output().stdout);
     let json: serde_json::Value = serde_json::from_str(&output).unwrap();
     let expected = serde_json::json!([{
         "type": "missing_newline",
+        "severity": "error",
         "line": 1,
         "file": file_path.to_string_lossy().to_string(),
     }]);
@@ -53,13 +80,14 @@ fn test_json_output() {
     let temp = tempfile::tempdir().unwrap();
     let file_path = temp.path().join("test3.txt");
     fs::write(&file_path, "foo \n").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--json");
     let assert = cmd.assert().failure();
     let output = String::from_utf8_lossy(&assert.get_output().stdout);
     let json: serde_json::Value = serde_json::from_str(&output).unwrap();
     let expected = serde_json::json!([{
         "type": "trailing_whitespace",
+        "severity": "error",
         "line": 1,
         "file": file_path.to_string_lossy().to_string(),
     }]);
@@ -72,7 +100,7 @@ fn test_lint_crlf_line_ending() {
     let temp = tempfile::tempdir().unwrap();
     let file_path = temp.path().join("test_crlf.txt");
     fs::write(&file_path, "foo\r\nbar\n").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--json");
     let assert = cmd.assert().failure();
     let output = String::from_utf8_lossy(&assert.get_output().stdout);
@@ -87,7 +115,7 @@ fn test_lint_multiple_blank_lines_eof() {
     let temp = tempfile::tempdir().unwrap();
     let file_path = temp.path().join("test_blank.txt");
     fs::write(&file_path, "foo\n\n\n").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--json");
     let assert = cmd.assert().failure();
     let output = String::from_utf8_lossy(&assert.get_output().stdout);
@@ -103,7 +131,7 @@ fn test_ignore_pattern() {
     let temp = tempfile::tempdir().unwrap();
     let file_path = temp.path().join("ignore.me");
     fs::write(&file_path, "foo \n").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path())
         .arg("--ignore")
         .arg("*.me");
@@ -118,7 +146,7 @@ fn test_output_to_file() {
     let file_path = temp.path().join("test_output.txt");
     fs::write(&file_path, "foo \n").unwrap();
     let output_path = temp.path().join("result.md");
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path())
         .arg("--output")
         .arg(&output_path);
@@ -132,7 +160,7 @@ fn test_yaml_output() {
     let temp = tempfile::tempdir().unwrap();
     let file_path = temp.path().join("test_yaml.txt");
     fs::write(&file_path, "foo \n").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--yaml");
     cmd.assert().failure();
 }
@@ -141,7 +169,7 @@ fn test_yaml_output() {
 #[test]
 fn test_invalid_directory() {
     // Should fail if directory does not exist
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg("/this/path/should/not/exist");
     cmd.assert().failure();
 }
@@ -151,7 +179,7 @@ fn test_invalid_directory() {
 fn test_empty_directory() {
     // Should succeed if directory is empty
     let temp = tempfile::tempdir().unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path());
     cmd.assert().success();
 }
@@ -166,7 +194,7 @@ fn test_multiple_dirs() {
     let file_path2 = temp2.path().join("b.txt");
     fs::write(&file_path1, "foo \n").unwrap();
     fs::write(&file_path2, "bar \n").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp1.path()).arg(temp2.path());
     cmd.assert().failure();
 }
@@ -178,10 +206,10 @@ fn test_gitignore_on_non_git_repo() {
     let file_path = temp.path().join("test_gitignore.txt");
     fs::write(&file_path, "foo \n").unwrap();
     // Do not create .git directory
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--git");
     // Should fail if .git directory is not present
-    cmd.assert().failure();
+    cmd.assert().code(3);
 }
 
 // Test: should fail with error about invalid glob pattern
@@ -191,10 +219,10 @@ fn test_invalid_glob_pattern() {
     let file_path = temp.path().join("test.txt");
     fs::write(&file_path, "foo \n").unwrap();
     // Invalid glob pattern
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--ignore").arg("[invalid");
     // Should fail with error about invalid glob
-    cmd.assert().failure();
+    cmd.assert().code(2);
 }
 
 // Test: should succeed if file is unreadable (no read permission)
@@ -208,7 +236,7 @@ fn test_unreadable_file() {
     let mut perms = fs::metadata(&file_path).unwrap().permissions();
     perms.set_mode(0o000);
     fs::set_permissions(&file_path, perms).unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path());
     // Should not panic or crash
     cmd.assert().success();
@@ -223,7 +251,7 @@ fn test_git_tracked_files_error_branch() {
     fs::write(&git_file, "not a git dir").unwrap();
     let file_path = temp.path().join("test.txt");
     fs::write(&file_path, "foo \n").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     // Do not use --gitignore, triggers is_git_repo==true but git_tracked_files fails
     cmd.arg(temp.path());
     cmd.assert()
@@ -237,7 +265,7 @@ fn test_should_ignore_invalid_glob() {
     let file_path = temp.path().join("badglob.txt");
     fs::write(&file_path, "foo \n").unwrap();
     // Pass an invalid glob, should_ignore's glob::Pattern::new will fail, branch is covered
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--ignore").arg("[bad[glob");
     // Should fail with error about invalid glob
     cmd.assert().failure();
@@ -249,11 +277,11 @@ fn test_is_char_boundary_fail() {
     let temp = tempfile::tempdir().unwrap();
     let file_path = temp.path().join("not_utf8.bin");
     // Write invalid utf-8 bytes
-    let mut f = std::fs::OpenOptions::new().write(true).create(true).open(&file_path).unwrap();
+    let mut f = std::fs::OpenOptions::new().write(true).create(true).truncate(true).open(&file_path).unwrap();
     use std::io::Write as _;
     f.write_all(b"foo \xFF\xFF\xFF\n").unwrap();
     drop(f);
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path());
     // Should not panic or crash
     cmd.assert().success();
@@ -271,7 +299,7 @@ fn test_output_file_no_permission() {
     let mut perms = fs::metadata(&output_path).unwrap().permissions();
     perms.set_mode(0o000);
     fs::set_permissions(&output_path, perms).unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--output").arg(&output_path);
     // Should not panic or crash
     cmd.assert().failure();
@@ -285,7 +313,7 @@ fn test_output_to_directory_should_fail() {
     fs::write(&file_path, "foo \n").unwrap();
     let output_dir = temp.path().join("outdir");
     fs::create_dir(&output_dir).unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path())
         .arg("--output")
         .arg(&output_dir);
@@ -300,7 +328,7 @@ fn test_broken_symlink() {
     let temp = tempfile::tempdir().unwrap();
     let broken = temp.path().join("broken.txt");
     symlink(temp.path().join("not_exist.txt"), &broken).unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path());
     // Should not panic, and output should be no issues
     cmd.assert().success();
@@ -313,7 +341,7 @@ fn test_binary_file() {
     let file_path = temp.path().join("binfile");
     // Write some non-UTF-8 bytes
     fs::write(&file_path, b"\xff\xfe\xfd\xfc").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path());
     // Should not panic, and output should be no issues
     cmd.assert().success();
@@ -322,7 +350,7 @@ fn test_binary_file() {
 // Test: should fail if empty path argument is given
 #[test]
 fn test_empty_path_argument() {
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg("");
     // Should fail immediately
     cmd.assert().failure();
@@ -334,7 +362,7 @@ fn test_output_to_dev_full() {
     let temp = tempfile::tempdir().unwrap();
     let file_path = temp.path().join("test.txt");
     fs::write(&file_path, "foo \n").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path())
         .arg("--output")
         .arg("/dev/full");
@@ -354,7 +382,7 @@ fn test_recursive_symlink_loop() {
     // dir1/loop -> ../dir2, dir2/loop -> ../dir1
     symlink(&dir2, dir1.join("loop")).unwrap();
     symlink(&dir1, dir2.join("loop")).unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(&dir1);
     // Should not deadlock or panic
     cmd.assert().success();
@@ -368,7 +396,7 @@ fn test_output_json_to_directory_should_fail() {
     fs::write(&file_path, "foo \n").unwrap();
     let output_dir = temp.path().join("outdir_json");
     fs::create_dir(&output_dir).unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path())
         .arg("--output")
         .arg(&output_dir)
@@ -389,7 +417,7 @@ fn test_output_yaml_to_no_permission_file() {
     let mut perms = fs::metadata(&output_path).unwrap().permissions();
     perms.set_mode(0o000);
     fs::set_permissions(&output_path, perms).unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--output").arg(&output_path).arg("--yaml");
     // Should not panic or crash
     cmd.assert().failure();
@@ -401,7 +429,7 @@ fn test_ignore_via_env() {
     let temp = tempfile::tempdir().unwrap();
     let file_path = temp.path().join("ignore.me");
     fs::write(&file_path, "foo \n").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).env("CLEAN_IGNORE", "*.me");
     cmd.assert().success();
 }
@@ -416,7 +444,7 @@ fn test_ignore_multiple_patterns_via_env() {
     fs::write(&file1, "foo \n").unwrap();
     fs::write(&file2, "bar \n").unwrap();
     fs::write(&file3, "baz \n").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).env("CLEAN_IGNORE", "*.md:*.log");
     // Only c.txt should be linted, but it has trailing whitespace so should fail
     let output = cmd.assert().failure().get_output().stdout.clone();
@@ -434,7 +462,7 @@ fn test_all_files_ignored() {
     let file_path2 = temp.path().join("b.txt");
     fs::write(&file_path, "foo \n").unwrap();
     fs::write(&file_path2, "bar \n").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--ignore").arg("*.txt");
     // All files should be ignored
     cmd.assert().success();
@@ -458,7 +486,7 @@ fn test_output_file_deleted_during_write() {
         thread::sleep(Duration::from_millis(10));
         let _ = std::fs::remove_file(&output_path2);
     });
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--output").arg(&output_path);
     // Should not panic or crash
     let _ = cmd.assert();
@@ -471,7 +499,7 @@ fn test_lint_trailing_whitespace_on_last_line() {
     let file_path = temp.path().join("test_last_line.txt");
     // The last line has trailing whitespace and no newline at the end
     fs::write(&file_path, "hello\nworld   ").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--json");
     cmd.assert().failure();
 }
@@ -489,7 +517,7 @@ fn test_git_auto_detect_only_tracked_files() {
     fs::write(&file_untracked, "bar \n").unwrap();
     SysCommand::new("git").arg("add").arg(&file_tracked).current_dir(temp.path()).output().unwrap();
     SysCommand::new("git").arg("commit").arg("-m").arg("add tracked").current_dir(temp.path()).output().unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path());
     let output = cmd.assert().failure().get_output().stdout.clone();
     let s = String::from_utf8_lossy(&output);
@@ -509,7 +537,7 @@ fn test_git_false_lint_all_files() {
     fs::write(&file_untracked, "bar \n").unwrap();
     SysCommand::new("git").arg("add").arg(&file_tracked).current_dir(temp.path()).output().unwrap();
     SysCommand::new("git").arg("commit").arg("-m").arg("add tracked").current_dir(temp.path()).output().unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--git=false");
     let output = cmd.assert().failure().get_output().stdout.clone();
     let s = String::from_utf8_lossy(&output);
@@ -527,7 +555,7 @@ fn test_ignore_subdirectory_without_dot_slash() {
     let file2 = subdir.join("nested.txt");
     fs::write(&file1, "foo \n").unwrap();
     fs::write(&file2, "bar \n").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path())
         .arg("--ignore")
         .arg("third_party/**");
@@ -546,7 +574,7 @@ fn test_git_true_only_tracked_files() {
     fs::write(&file_untracked, "bar \n").unwrap();
     SysCommand::new("git").arg("add").arg(&file_tracked).current_dir(temp.path()).output().unwrap();
     SysCommand::new("git").arg("commit").arg("-m").arg("add tracked").current_dir(temp.path()).output().unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--git");
     let output = cmd.assert().failure().get_output().stdout.clone();
     let s = String::from_utf8_lossy(&output);
@@ -561,7 +589,7 @@ fn test_fix_all_issues() {
     let file_path = temp.path().join("test.txt");
     // File with: trailing whitespace, CRLF, missing newline, multiple blank lines
     fs::write(&file_path, "hello   \r\nworld\r\n\n\n").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--fix");
     cmd.assert().success();
     let fixed = fs::read_to_string(&file_path).unwrap();
@@ -575,7 +603,7 @@ fn test_fix_clean_file_unchanged() {
     let file_path = temp.path().join("clean.txt");
     let original = "hello\nworld\n";
     fs::write(&file_path, original).unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--fix");
     cmd.assert().success();
     let after = fs::read_to_string(&file_path).unwrap();
@@ -589,11 +617,11 @@ fn test_fix_then_lint_passes() {
     let file_path = temp.path().join("test.txt");
     fs::write(&file_path, "hello   \r\nworld\r\n\n\n").unwrap();
     // First, fix
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--fix");
     cmd.assert().success();
     // Then, lint
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path());
     cmd.assert().success();
 }
@@ -604,9 +632,1912 @@ fn test_fix_empty_file() {
     let temp = tempfile::tempdir().unwrap();
     let file_path = temp.path().join("empty.txt");
     fs::write(&file_path, "").unwrap();
-    let mut cmd = Command::cargo_bin("clean").unwrap();
+    let mut cmd = clean();
     cmd.arg(temp.path()).arg("--fix");
     cmd.assert().success();
     let after = fs::read_to_string(&file_path).unwrap();
     assert_eq!(after, "");
 }
+
+// Test: should lint an individual file given as argument and report its given path
+#[test]
+fn test_lint_single_file_argument() {
+    let temp = tempfile::tempdir().unwrap();
+    let file_path = temp.path().join("single.txt");
+    let other_path = temp.path().join("other.txt");
+    fs::write(&file_path, "foo \n").unwrap();
+    fs::write(&other_path, "bar \n").unwrap();
+    let mut cmd = clean();
+    cmd.arg(&file_path).arg("--json");
+    let assert = cmd.assert().failure();
+    let output = String::from_utf8_lossy(&assert.get_output().stdout);
+    let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let expected = serde_json::json!([{
+        "type": "trailing_whitespace",
+        "severity": "error",
+        "line": 1,
+        "file": file_path.to_string_lossy().to_string(),
+    }]);
+    assert_eq!(json, expected);
+}
+
+// Test: should read NUL-delimited paths from stdin with --files-from - --null
+#[test]
+fn test_files_from_stdin_null() {
+    let temp = tempfile::tempdir().unwrap();
+    let file1 = temp.path().join("a.txt");
+    let file2 = temp.path().join("b.md");
+    let file3 = temp.path().join("c.txt");
+    fs::write(&file1, "foo \n").unwrap();
+    fs::write(&file2, "bar \n").unwrap();
+    fs::write(&file3, "baz \n").unwrap();
+    let input = format!(
+        "{}\0{}\0",
+        file1.to_string_lossy(),
+        file2.to_string_lossy()
+    );
+    let mut cmd = clean();
+    cmd.arg("--files-from")
+        .arg("-")
+        .arg("--null")
+        .arg("--ignore")
+        .arg("*.md")
+        .write_stdin(input);
+    let output = cmd.assert().failure().get_output().stdout.clone();
+    let s = String::from_utf8_lossy(&output);
+    assert!(s.contains("a.txt"));
+    // Ignore rules still apply to listed files
+    assert!(!s.contains("b.md"));
+    // Directories are not walked when --files-from is given
+    assert!(!s.contains("c.txt"));
+}
+
+// Test: should read newline-delimited paths from a file with --files-from
+#[test]
+fn test_files_from_file() {
+    let temp = tempfile::tempdir().unwrap();
+    let file_path = temp.path().join("clean.txt");
+    fs::write(&file_path, "foo\n").unwrap();
+    let list_path = temp.path().join("list");
+    fs::write(&list_path, format!("{}\n", file_path.to_string_lossy())).unwrap();
+    let mut cmd = clean();
+    cmd.arg("--files-from").arg(&list_path);
+    cmd.assert().success();
+}
+
+// Test: listed files that do not exist are skipped with a warning
+#[test]
+fn test_files_from_missing_file() {
+    let temp = tempfile::tempdir().unwrap();
+    let file_path = temp.path().join("a.txt");
+    fs::write(&file_path, "foo \n").unwrap();
+    let input = format!(
+        "{}\n{}\n",
+        temp.path().join("missing.txt").to_string_lossy(),
+        file_path.to_string_lossy()
+    );
+    let mut cmd = clean();
+    cmd.arg("--files-from").arg("-").write_stdin(input);
+    let output = cmd.assert().code(1).get_output().clone();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("a.txt"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("listed file not found, skipped"));
+    assert!(stderr.contains("missing.txt"));
+}
+
+// Test: should lint stdin and report issues with the virtual file name
+#[test]
+fn test_stdin_lint_with_filename() {
+    let mut cmd = clean();
+    cmd.arg("--stdin")
+        .arg("--stdin-filename")
+        .arg("src/virtual.txt")
+        .arg("--json")
+        .write_stdin("hello \nworld\n");
+    let assert = cmd.assert().failure();
+    let output = String::from_utf8_lossy(&assert.get_output().stdout);
+    let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let expected = serde_json::json!([{
+        "type": "trailing_whitespace",
+        "severity": "error",
+        "line": 1,
+        "file": "src/virtual.txt",
+    }]);
+    assert_eq!(json, expected);
+}
+
+// Test: should apply ignore rules to the stdin file name
+#[test]
+fn test_stdin_ignored_filename() {
+    let mut cmd = clean();
+    cmd.arg("--stdin")
+        .arg("--stdin-filename")
+        .arg("docs/readme.md")
+        .arg("--ignore")
+        .arg("*.md")
+        .write_stdin("hello \n");
+    cmd.assert().success();
+}
+
+// Test: --stdin --fix should act as a filter and write fixed content to stdout
+#[test]
+fn test_stdin_fix_filter() {
+    let mut cmd = clean();
+    cmd.arg("--stdin")
+        .arg("--fix")
+        .write_stdin("hello   \r\nworld\r\n\n\n");
+    cmd.assert().success().stdout("hello\nworld\n");
+}
+
+// Test: filter-process should normalize content staged through a git filter driver
+#[test]
+fn test_filter_process_git_add() {
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    let bin = assert_cmd::cargo::cargo_bin("clean");
+    SysCommand::new("git").arg("init").current_dir(temp.path()).output().unwrap();
+    SysCommand::new("git")
+        .arg("config")
+        .arg("filter.clean.process")
+        .arg(format!("{} filter-process --ignore *.keep", bin.display()))
+        .current_dir(temp.path())
+        .output()
+        .unwrap();
+    SysCommand::new("git")
+        .arg("config")
+        .arg("filter.clean.required")
+        .arg("true")
+        .current_dir(temp.path())
+        .output()
+        .unwrap();
+    fs::write(temp.path().join(".gitattributes"), "*.txt filter=clean\n*.keep filter=clean\n").unwrap();
+    fs::write(temp.path().join("dirty.txt"), "hello   \r\nworld\r\n\n\n").unwrap();
+    fs::write(temp.path().join("ignored.keep"), "hello   \n").unwrap();
+    let status = SysCommand::new("git")
+        .envs(xdg_env())
+        .arg("add")
+        .arg(".")
+        .current_dir(temp.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let staged = SysCommand::new("git")
+        .arg("show")
+        .arg(":dirty.txt")
+        .current_dir(temp.path())
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&staged.stdout), "hello\nworld\n");
+    let staged = SysCommand::new("git")
+        .arg("show")
+        .arg(":ignored.keep")
+        .current_dir(temp.path())
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&staged.stdout), "hello   \n");
+    // The working tree is left untouched
+    let worktree = fs::read_to_string(temp.path().join("dirty.txt")).unwrap();
+    assert_eq!(worktree, "hello   \r\nworld\r\n\n\n");
+}
+
+// Test: filter-process answers a blob it can not clean with an error, and
+// goes on with the next one
+#[test]
+fn test_filter_process_blob_error() {
+    let temp = tempfile::tempdir().unwrap();
+    let config = "[[plugins]]\nid = \"broken\"\nmessage = \"Broken\"\ncommand = [\"sh\", \"-c\", \"exit 3\"]\nfiles = [\"*.sh\"]\n";
+    fs::write(temp.path().join(".clean.toml"), config).unwrap();
+    let packet = |data: &str| format!("{:04x}{}", data.len() + 4, data);
+    let request = |path: &str, content: &str| {
+        format!(
+            "{}{}0000{}0000",
+            packet("command=clean\n"),
+            packet(&format!("pathname={}\n", path)),
+            packet(content)
+        )
+    };
+    let input = format!(
+        "{}{}0000{}{}0000{}{}",
+        packet("git-filter-client\n"),
+        packet("version=2\n"),
+        packet("capability=clean\n"),
+        packet("capability=smudge\n"),
+        request("a.sh", "echo  \n"),
+        request("b.txt", "b  \n"),
+    );
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("filter-process").write_stdin(input);
+    let assert = cmd.assert().success();
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let replies = stdout.split_once("capability=clean\n0000").unwrap().1;
+    assert_eq!(
+        replies,
+        format!("{}0000{}0000{}00000000", packet("status=error\n"), packet("status=success\n"), packet("b\n"))
+    );
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("failed to clean 'a.sh'"), "{}", stderr);
+}
+
+// Test: filter-process should reject an invalid handshake
+#[test]
+fn test_filter_process_invalid_handshake() {
+    let mut cmd = clean();
+    cmd.arg("filter-process").write_stdin("0014not-a-git-client0000");
+    cmd.assert().failure();
+}
+
+// Test: should detect space before tab in indent by default, like git
+#[test]
+fn test_lint_space_before_tab() {
+    let temp = tempfile::tempdir().unwrap();
+    let file_path = temp.path().join("test.txt");
+    fs::write(&file_path, "ok\n \tbad\n").unwrap();
+    let mut cmd = clean();
+    cmd.arg(temp.path()).arg("--json");
+    let assert = cmd.assert().failure();
+    let output = String::from_utf8_lossy(&assert.get_output().stdout);
+    let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let expected = serde_json::json!([{
+        "type": "space_before_tab",
+        "severity": "error",
+        "line": 2,
+        "file": file_path.to_string_lossy().to_string(),
+    }]);
+    assert_eq!(json, expected);
+}
+
+// Test: should follow core.whitespace from git config, for linting and fixing
+#[test]
+fn test_core_whitespace_tab_in_indent() {
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    SysCommand::new("git").arg("init").current_dir(temp.path()).output().unwrap();
+    SysCommand::new("git")
+        .arg("config")
+        .arg("core.whitespace")
+        .arg("tab-in-indent,tabwidth=4,cr-at-eol")
+        .current_dir(temp.path())
+        .output()
+        .unwrap();
+    let file_path = temp.path().join("test.txt");
+    fs::write(&file_path, "a\r\n\tb\r\n").unwrap();
+    SysCommand::new("git").arg("add").arg(&file_path).current_dir(temp.path()).output().unwrap();
+    let mut cmd = clean();
+    cmd.arg(temp.path()).arg("--json");
+    let assert = cmd.assert().failure();
+    let output = String::from_utf8_lossy(&assert.get_output().stdout);
+    let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+    // cr-at-eol accepts CRLF, so only the tab is reported
+    let expected = serde_json::json!([{
+        "type": "tab_in_indent",
+        "severity": "error",
+        "line": 2,
+        "file": file_path.to_string_lossy().to_string(),
+    }]);
+    assert_eq!(json, expected);
+    let mut cmd = clean();
+    cmd.arg(temp.path()).arg("--fix");
+    cmd.assert().success();
+    let fixed = fs::read_to_string(&file_path).unwrap();
+    assert_eq!(fixed, "a\r\n    b\r\n");
+}
+
+// Test: should honor the whitespace attribute from .gitattributes
+#[test]
+fn test_whitespace_attribute() {
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    SysCommand::new("git").arg("init").current_dir(temp.path()).output().unwrap();
+    fs::write(
+        temp.path().join(".gitattributes"),
+        "fixtures/* -whitespace\n*.c whitespace=indent-with-non-tab\n",
+    )
+    .unwrap();
+    fs::create_dir(temp.path().join("fixtures")).unwrap();
+    fs::write(temp.path().join("fixtures").join("expected.txt"), "foo \n\n\n").unwrap();
+    let source = temp.path().join("main.c");
+    fs::write(&source, "int main() {\n        return 0;\n}\n").unwrap();
+    SysCommand::new("git").arg("add").arg(".").current_dir(temp.path()).output().unwrap();
+    let mut cmd = clean();
+    cmd.arg(temp.path()).arg("--json");
+    let assert = cmd.assert().failure();
+    let output = String::from_utf8_lossy(&assert.get_output().stdout);
+    let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let expected = serde_json::json!([{
+        "type": "indent_with_non_tab",
+        "severity": "error",
+        "line": 2,
+        "file": source.to_string_lossy().to_string(),
+    }]);
+    assert_eq!(json, expected);
+}
+
+// Test: invalid whitespace settings are warned about and replaced, rather
+// than failing
+#[test]
+fn test_invalid_whitespace_settings() {
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    SysCommand::new("git").arg("init").current_dir(temp.path()).output().unwrap();
+    fs::write(temp.path().join(".gitattributes"), "*.txt whitespace=tabwidth=99\n").unwrap();
+    fs::write(temp.path().join("a.txt"), "a \n").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).args(["--json", "a.txt"]);
+    let assert = cmd.assert().code(1);
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(json[0]["type"], "trailing_whitespace");
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("invalid whitespace attribute"), "{}", stderr);
+
+    SysCommand::new("git")
+        .args(["config", "core.whitespace", "tab-in-indent,indent-with-non-tab"])
+        .current_dir(temp.path())
+        .output()
+        .unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).args(["--json", "a.txt"]);
+    let assert = cmd.assert().code(1);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("invalid core.whitespace"), "{}", stderr);
+
+    // A name outside of the repository gets core.whitespace.
+    let mut cmd = clean();
+    cmd.current_dir(temp.path())
+        .args(["--stdin", "--stdin-filename", "/elsewhere/b.txt", "--json"])
+        .write_stdin("b \n");
+    let assert = cmd.assert().code(1);
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(json[0]["file"], "/elsewhere/b.txt");
+}
+
+// Test: should skip or adjust checks based on text, binary, eol and clean attributes
+#[test]
+fn test_gitattributes_text_binary_eol_clean() {
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    SysCommand::new("git").arg("init").current_dir(temp.path()).output().unwrap();
+    fs::write(
+        temp.path().join(".gitattributes"),
+        "*.bin binary\n*.dat -text\n*.bat eol=crlf\nskip.txt -clean\nforce.dat clean\n",
+    )
+    .unwrap();
+    fs::write(temp.path().join("data.bin"), "foo \n").unwrap();
+    fs::write(temp.path().join("data.dat"), "foo \n").unwrap();
+    fs::write(temp.path().join("script.bat"), "echo\r\n").unwrap();
+    fs::write(temp.path().join("skip.txt"), "foo \n").unwrap();
+    let forced = temp.path().join("force.dat");
+    fs::write(&forced, "foo \n").unwrap();
+    SysCommand::new("git").arg("add").arg(".").current_dir(temp.path()).output().unwrap();
+    let mut cmd = clean();
+    cmd.arg(temp.path()).arg("--json");
+    let assert = cmd.assert().failure();
+    let output = String::from_utf8_lossy(&assert.get_output().stdout);
+    let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let expected = serde_json::json!([{
+        "type": "trailing_whitespace",
+        "severity": "error",
+        "line": 1,
+        "file": forced.to_string_lossy().to_string(),
+    }]);
+    assert_eq!(json, expected);
+}
+
+// Test: --fix should keep CRLF line endings of eol=crlf files
+#[test]
+fn test_fix_keeps_crlf_for_eol_crlf() {
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    SysCommand::new("git").arg("init").current_dir(temp.path()).output().unwrap();
+    fs::write(temp.path().join(".gitattributes"), "*.bat eol=crlf\n").unwrap();
+    let file_path = temp.path().join("script.bat");
+    fs::write(&file_path, "echo  \r\n\r\n\r\n").unwrap();
+    SysCommand::new("git").arg("add").arg(".").current_dir(temp.path()).output().unwrap();
+    let mut cmd = clean();
+    cmd.arg(temp.path()).arg("--fix");
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "echo\r\n");
+}
+
+// Test: commit-msg should report issues in the message but ignore comment lines
+#[test]
+fn test_commit_msg_ignores_comments() {
+    let temp = tempfile::tempdir().unwrap();
+    let msg = temp.path().join("COMMIT_EDITMSG");
+    fs::write(&msg, "subject \n\nbody\n\n# Please enter the commit message \n#\n").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("commit-msg").arg(&msg);
+    let assert = cmd.assert().failure();
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains(&format!("## {}", msg.display())));
+    assert!(stdout.contains("- **Line:** `1` error: Trailing whitespace"));
+    assert!(!stdout.contains("`5`"));
+}
+
+// Test: commit-msg uses the rules and severities of .clean.toml, and
+// --fail-on
+#[test]
+fn test_commit_msg_config() {
+    let temp = tempfile::tempdir().unwrap();
+    let config = "[[rules]]\nid = \"no_wip\"\nmessage = \"WIP commit\"\npattern = \"^WIP\"\nseverity = \"warning\"\n";
+    fs::write(temp.path().join(".clean.toml"), config).unwrap();
+    let msg = temp.path().join("COMMIT_EDITMSG");
+    fs::write(&msg, "WIP subject\n").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("commit-msg").arg(&msg);
+    let assert = cmd.assert().code(1);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(
+        stdout.contains("- **Line:** `1` warning: WIP commit"),
+        "{}",
+        stdout
+    );
+
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).args(["--fail-on", "error", "--json", "commit-msg"]).arg(&msg);
+    let assert = cmd.assert().success();
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(json[0]["type"], "no_wip");
+    assert_eq!(json[0]["severity"], "warning");
+
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).args(["--skip", "no_wip", "commit-msg"]).arg(&msg);
+    cmd.assert().success();
+}
+
+// Test: commit-msg should ignore everything below the scissors line
+#[test]
+fn test_commit_msg_scissors() {
+    let temp = tempfile::tempdir().unwrap();
+    let msg = temp.path().join("COMMIT_EDITMSG");
+    fs::write(
+        &msg,
+        "subject\n# ------------------------ >8 ------------------------\ndiff \n\n\n",
+    )
+    .unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("commit-msg").arg(&msg);
+    cmd.assert().success();
+}
+
+// Test: commit-msg --fix should fix the message in place and keep comments
+#[test]
+fn test_commit_msg_fix() {
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    SysCommand::new("git").arg("init").current_dir(temp.path()).output().unwrap();
+    SysCommand::new("git")
+        .arg("config")
+        .arg("core.commentChar")
+        .arg(";")
+        .current_dir(temp.path())
+        .output()
+        .unwrap();
+    let msg = temp.path().join("COMMIT_EDITMSG");
+    fs::write(&msg, "subject  \r\n\r\nbody\t\n\n; comment \n").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("commit-msg").arg("--fix").arg(&msg);
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&msg).unwrap(), "subject\n\nbody\n\n; comment \n");
+    // A message without comments gets exactly one trailing newline
+    fs::write(&msg, "subject\n\n\n").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("commit-msg").arg("--fix").arg(&msg);
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&msg).unwrap(), "subject\n");
+    // Blank lines at the end and a missing newline are fixed too
+    fs::write(&msg, "subject\n\nbody\n\n\n; comment").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("commit-msg").arg("--fix").arg(&msg);
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&msg).unwrap(), "subject\n\nbody\n\n\n; comment");
+    fs::write(&msg, "subject\n\nbody").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("commit-msg").arg("--fix").arg(&msg);
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&msg).unwrap(), "subject\n\nbody\n");
+    fs::write(&msg, "subject\n\n\n\n").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("commit-msg").arg(&msg);
+    cmd.assert().failure();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("commit-msg").arg("--fix").arg(&msg);
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&msg).unwrap(), "subject\n");
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("commit-msg").arg(&msg);
+    cmd.assert().success();
+}
+
+// Test: --staged should lint the staged content, not the working tree
+#[test]
+fn test_staged_lints_index_content() {
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    SysCommand::new("git").arg("init").current_dir(temp.path()).output().unwrap();
+    let file_path = temp.path().join("staged.txt");
+    fs::write(&file_path, "foo \n").unwrap();
+    SysCommand::new("git").arg("add").arg(&file_path).current_dir(temp.path()).output().unwrap();
+    fs::write(&file_path, "foo\n").unwrap();
+    fs::write(temp.path().join("unstaged.txt"), "bar \n").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("--staged").arg("--json");
+    let assert = cmd.assert().failure();
+    let output = String::from_utf8_lossy(&assert.get_output().stdout);
+    let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let expected = serde_json::json!([{
+        "type": "trailing_whitespace",
+        "severity": "error",
+        "line": 1,
+        "file": "staged.txt",
+    }]);
+    assert_eq!(json, expected);
+}
+
+// Test: install-hook should chain into an existing hook and uninstall cleanly
+#[test]
+fn test_install_hook_chain_and_uninstall() {
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    SysCommand::new("git").arg("init").current_dir(temp.path()).output().unwrap();
+    let hook = temp.path().join(".git").join("hooks").join("pre-commit");
+    fs::create_dir_all(hook.parent().unwrap()).unwrap();
+    let original = "#!/bin/sh\necho existing\nexec true\n";
+    fs::write(&hook, original).unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("install-hook").arg("pre-commit");
+    cmd.assert().success();
+    let installed = fs::read_to_string(&hook).unwrap();
+    assert!(installed.starts_with("#!/bin/sh\n# >>> clean >>>\n"));
+    assert!(installed.contains("--staged"));
+    assert!(installed.ends_with("echo existing\nexec true\n"));
+    // Installing twice does not duplicate the block
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("install-hook");
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&hook).unwrap(), installed);
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("install-hook").arg("--uninstall");
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&hook).unwrap(), original);
+}
+
+// Test: install-hook should honor core.hooksPath and remove hooks it created
+#[test]
+fn test_install_hook_hooks_path() {
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    SysCommand::new("git").arg("init").current_dir(temp.path()).output().unwrap();
+    SysCommand::new("git")
+        .arg("config")
+        .arg("core.hooksPath")
+        .arg("custom-hooks")
+        .current_dir(temp.path())
+        .output()
+        .unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("install-hook").arg("commit-msg");
+    cmd.assert().success();
+    let hook = temp.path().join("custom-hooks").join("commit-msg");
+    assert!(fs::read_to_string(&hook).unwrap().contains("commit-msg \"$1\""));
+    let mut cmd = clean();
+    cmd.current_dir(temp.path())
+        .arg("install-hook")
+        .arg("commit-msg")
+        .arg("--uninstall");
+    cmd.assert().success();
+    assert!(!hook.exists());
+}
+
+// Test: pre-push should lint files changed by the pushed commits
+#[test]
+fn test_pre_push_lints_pushed_commits() {
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    let git = |args: &[&str]| {
+        let output = SysCommand::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(temp.path())
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+    git(&["init"]);
+    fs::write(temp.path().join("old.txt"), "old \n").unwrap();
+    git(&["add", "old.txt"]);
+    git(&["commit", "-m", "old"]);
+    let base = git(&["rev-parse", "HEAD"]);
+    fs::write(temp.path().join("new.txt"), "new \n").unwrap();
+    git(&["add", "new.txt"]);
+    git(&["commit", "-m", "new"]);
+    let head = git(&["rev-parse", "HEAD"]);
+    let mut cmd = clean();
+    cmd.current_dir(temp.path())
+        .arg("pre-push")
+        .arg("origin")
+        .arg("url")
+        .write_stdin(format!("refs/heads/master {} refs/heads/master {}\n", head, base));
+    let output = cmd.assert().failure().get_output().stdout.clone();
+    let s = String::from_utf8_lossy(&output);
+    assert!(s.contains("new.txt"));
+    assert!(!s.contains("old.txt"));
+}
+
+// Test: parallel linting should produce the same report, sorted by file and line
+#[test]
+fn test_parallel_jobs_deterministic_output() {
+    let temp = tempfile::tempdir().unwrap();
+    for i in 0..50 {
+        let sub = temp.path().join(format!("dir{}", i % 5));
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(sub.join(format!("file{:02}.txt", i)), "a \nb\nc \n").unwrap();
+    }
+    let run = |jobs: &str| {
+        let mut cmd = clean();
+        cmd.arg(temp.path()).arg("--json").arg("--jobs").arg(jobs);
+        cmd.assert().failure().get_output().stdout.clone()
+    };
+    let sequential = run("1");
+    assert_eq!(sequential, run("8"));
+    let json: serde_json::Value = serde_json::from_slice(&sequential).unwrap();
+    let keys: Vec<(String, u64)> = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| (i["file"].as_str().unwrap().to_string(), i["line"].as_u64().unwrap()))
+        .collect();
+    assert_eq!(keys.len(), 100);
+    let mut sorted = keys.clone();
+    sorted.sort();
+    assert_eq!(keys, sorted);
+}
+
+// Test: files above the streaming threshold give the same results as small ones
+#[test]
+fn test_large_file_streaming() {
+    use std::os::unix::fs::PermissionsExt;
+    let temp = tempfile::tempdir().unwrap();
+    let file = temp.path().join("large.txt");
+    let clean_line = "x".repeat(99) + "\n";
+    let mut content = String::from("first \r\n");
+    content.push_str(&clean_line.repeat(90_000));
+    content.push_str(" \tindent\nlast \n\n\n");
+    fs::write(&file, &content).unwrap();
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+    assert!(content.len() > 8 * 1024 * 1024);
+
+    let mut cmd = clean();
+    cmd.arg(&file).arg("--json");
+    let output = cmd.assert().failure().get_output().stdout.clone();
+    let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    let issues: Vec<(String, u64)> = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| (i["type"].as_str().unwrap().to_string(), i["line"].as_u64().unwrap()))
+        .collect();
+    assert_eq!(
+        issues,
+        vec![
+            ("trailing_whitespace".to_string(), 1),
+            ("crlf_line_ending".to_string(), 1),
+            ("space_before_tab".to_string(), 90_002),
+            ("trailing_whitespace".to_string(), 90_003),
+            ("multiple_blank_lines_eof".to_string(), 90_006),
+        ]
+    );
+
+    let mut cmd = clean();
+    cmd.arg(&file).arg("--fix");
+    let output = cmd.assert().success().get_output().stderr.clone();
+    // Too large to be saved for `clean undo`
+    assert!(String::from_utf8_lossy(&output).contains("it is larger than 8 MiB"));
+    assert!(!xdg_env()[0].1.join("clean").exists());
+    let mut expected = String::from("first\n");
+    expected.push_str(&clean_line.repeat(90_000));
+    expected.push_str("\tindent\nlast\n");
+    assert!(fs::read_to_string(&file).unwrap() == expected);
+    let mode = fs::metadata(&file).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+    assert_eq!(fs::read_dir(temp.path()).unwrap().count(), 1);
+}
+
+// Test: a large file whose issues can not be fixed is not rewritten
+#[test]
+fn test_large_file_unfixable() {
+    use std::os::unix::fs::MetadataExt;
+    let temp = tempfile::tempdir().unwrap();
+    let file = temp.path().join("large.txt");
+    let mut content = String::from("# clean:ignore-next-line trailing_whitespace\n");
+    content.push_str(&("x".repeat(99) + "\n").repeat(90_000));
+    fs::write(&file, &content).unwrap();
+    let inode = fs::metadata(&file).unwrap().ino();
+
+    let mut cmd = clean();
+    cmd.arg(&file).arg("--fix").arg("--json");
+    let output = cmd.assert().code(1).get_output().stdout.clone();
+    let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(json["fixed"], serde_json::json!([]));
+    assert_eq!(json["issues"][0]["type"], "unused_suppression");
+    assert_eq!(fs::metadata(&file).unwrap().ino(), inode);
+    // Nothing was saved for `clean undo`.
+    let state = xdg_env()[0].1.clone();
+    assert!(!state.join("clean").exists());
+}
+
+// Test: trailing non-ASCII whitespace is found, non-UTF-8 files are skipped
+#[test]
+fn test_unicode_whitespace_and_invalid_utf8() {
+    let temp = tempfile::tempdir().unwrap();
+    fs::write(temp.path().join("wide.txt"), "naïve\u{3000}\nok\n").unwrap();
+    fs::write(temp.path().join("latin1.txt"), b"caf\xe9 \n").unwrap();
+    let mut cmd = clean();
+    cmd.arg(temp.path()).arg("--json");
+    let output = cmd.assert().failure().get_output().clone();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let issues = json.as_array().unwrap();
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0]["file"].as_str().unwrap().ends_with("wide.txt"));
+    assert_eq!(issues[0]["type"], "trailing_whitespace");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not a valid UTF-8"));
+}
+
+// Test: results of unchanged files are read from the cache
+#[test]
+fn test_result_cache() {
+    let temp = tempfile::tempdir().unwrap();
+    let cache = temp.path().join("cache");
+    let dir = temp.path().join("src");
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("a.txt"), "a \n").unwrap();
+    let run = |args: &[&str]| {
+        let mut cmd = clean();
+        cmd.env("XDG_CACHE_HOME", &cache).arg(&dir).args(args);
+        cmd.assert()
+    };
+    run(&["--no-cache"]).failure();
+    assert!(!cache.exists());
+    let output = run(&[]).failure().get_output().stdout.clone();
+    assert!(String::from_utf8_lossy(&output).contains("a.txt"));
+    let entries = cache_entries(&cache);
+    assert_eq!(entries.len(), 1);
+    // A tampered entry proves the cached result is used.
+    fs::write(&entries[0], "[]").unwrap();
+    run(&[]).success();
+    run(&["--no-cache"]).failure();
+    // Changed content is linted again.
+    fs::write(dir.join("a.txt"), "b \n").unwrap();
+    run(&[]).failure();
+
+    let mut cmd = clean();
+    cmd.env("XDG_CACHE_HOME", &cache).arg("cache").arg("clear");
+    cmd.assert().success();
+    assert!(!cache.join("clean").exists());
+}
+
+// Test: cache entries unused for a long time are removed, used ones are kept
+#[test]
+fn test_result_cache_prune() {
+    let temp = tempfile::tempdir().unwrap();
+    let cache = temp.path().join("cache");
+    fs::write(temp.path().join("a.txt"), "a \n").unwrap();
+    let run = || {
+        let mut cmd = clean();
+        cmd.env("XDG_CACHE_HOME", &cache)
+            .arg(temp.path().join("a.txt"));
+        cmd.assert().failure();
+    };
+    run();
+    let used = cache_entries(&cache).remove(0);
+    let unused = cache.join("clean/00/unused");
+    fs::create_dir_all(unused.parent().unwrap()).unwrap();
+    fs::write(&unused, "[]").unwrap();
+    let age =
+        |days: u64| std::time::SystemTime::now() - std::time::Duration::from_secs(days * 86400);
+    let set_modified = |path: &std::path::Path, days: u64| {
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(age(days)).unwrap();
+    };
+    set_modified(&used, 40);
+    set_modified(&unused, 40);
+    // Pruned at most once a day.
+    run();
+    assert!(unused.exists());
+    let modified = |path: &std::path::Path| fs::metadata(path).unwrap().modified().unwrap();
+    assert!(modified(&used) > age(1));
+    set_modified(&cache.join("clean/.pruned"), 2);
+    run();
+    assert!(used.exists());
+    assert!(!unused.exists());
+    assert!(!unused.parent().unwrap().exists());
+}
+
+/// Returns the entries of the result cache in `cache`.
+fn cache_entries(cache: &std::path::Path) -> Vec<std::path::PathBuf> {
+    files_in(&cache.join("clean"))
+        .into_iter()
+        .filter(|p| !p.ends_with(".pruned"))
+        .collect()
+}
+
+fn files_in(dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(files_in(&path));
+        } else {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Runs `clean --watch` in `dir`, returning the child and its stdout lines.
+fn spawn_watch(
+    dir: &std::path::Path,
+    args: &[&str],
+) -> (std::process::Child, std::sync::mpsc::Receiver<String>) {
+    use std::io::BufRead;
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("clean"))
+        .envs(xdg_env())
+        .current_dir(dir)
+        .arg("--watch")
+        .arg("--no-cache")
+        .args(args)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let stdout = std::io::BufReader::new(child.stdout.take().unwrap());
+    let stderr = std::io::BufReader::new(child.stderr.take().unwrap());
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in stdout.lines().map_while(Result::ok) {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    // Wait until the watcher is set up, and keep reading stderr after that.
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in stderr.lines().map_while(Result::ok) {
+            if line.contains("watching for changes") {
+                let _ = ready_tx.send(());
+            }
+        }
+    });
+    ready_rx
+        .recv_timeout(std::time::Duration::from_secs(10))
+        .unwrap();
+    (child, rx)
+}
+
+/// Waits for a line containing `text`, returning the lines read before it.
+fn wait_for_line(rx: &std::sync::mpsc::Receiver<String>, text: &str) -> Vec<String> {
+    let mut seen = Vec::new();
+    while let Ok(line) = rx.recv_timeout(std::time::Duration::from_secs(10)) {
+        if line.contains(text) {
+            return seen;
+        }
+        seen.push(line);
+    }
+    panic!("timed out waiting for {:?}, got {:?}", text, seen);
+}
+
+// Test: watch mode lints changed files again, respecting ignore rules
+#[test]
+fn test_watch_relints_changed_files() {
+    let temp = tempfile::tempdir().unwrap();
+    fs::write(temp.path().join("a.txt"), "a \n").unwrap();
+    let (mut child, rx) = spawn_watch(temp.path(), &["--ignore", "*.log", "."]);
+    wait_for_line(&rx, "./a.txt");
+    fs::write(temp.path().join("c.log"), "ignored \n").unwrap();
+    fs::write(temp.path().join("b.txt"), "b \n").unwrap();
+    let before = wait_for_line(&rx, "./b.txt");
+    fs::write(temp.path().join("a.txt"), "a\n").unwrap();
+    fs::write(temp.path().join("b.txt"), "b\n").unwrap();
+    let before = [before, wait_for_line(&rx, "No lint issues found.")].concat();
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(!before.iter().any(|l| l.contains("c.log")));
+}
+
+// Test: watch mode with --fix fixes files as they are saved
+#[test]
+fn test_watch_fix() {
+    let temp = tempfile::tempdir().unwrap();
+    let file = temp.path().join("a.txt");
+    fs::write(&file, "a\n").unwrap();
+    let (mut child, _rx) = spawn_watch(temp.path(), &["--fix", "."]);
+    fs::write(&file, "a \n\n\n").unwrap();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while fs::read_to_string(&file).unwrap() != "a\n" && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert_eq!(fs::read_to_string(&file).unwrap(), "a\n");
+}
+
+// Test: watch mode in a git repository only reports tracked files
+#[test]
+fn test_watch_git_tracked_only() {
+    let temp = tempfile::tempdir().unwrap();
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(args)
+            .current_dir(temp.path())
+            .output()
+            .unwrap();
+    };
+    git(&["init"]);
+    fs::write(temp.path().join("tracked.txt"), "ok\n").unwrap();
+    git(&["add", "tracked.txt"]);
+    let (mut child, rx) = spawn_watch(temp.path(), &["."]);
+    wait_for_line(&rx, "No lint issues found.");
+    fs::write(temp.path().join("untracked.txt"), "u \n").unwrap();
+    fs::write(temp.path().join("tracked.txt"), "t \n").unwrap();
+    let before = wait_for_line(&rx, "./tracked.txt");
+    let after = wait_for_line(&rx, "Trailing whitespace");
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(![before, after].concat().iter().any(|l| l.contains("untracked")));
+}
+
+/// Frames a JSON-RPC message for the language server protocol.
+fn lsp_message(value: serde_json::Value) -> String {
+    let body = value.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+// Test: the language server publishes diagnostics over stdio
+#[test]
+fn test_lsp_stdio() {
+    let messages = [
+        serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}}),
+        serde_json::json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+        serde_json::json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+            "textDocument": {"uri": "file:///tmp/a.txt", "languageId": "plaintext", "version": 1, "text": "a \n"}
+        }}),
+        serde_json::json!({"jsonrpc": "2.0", "id": 2, "method": "shutdown"}),
+        serde_json::json!({"jsonrpc": "2.0", "method": "exit"}),
+    ];
+    let input: String = messages.into_iter().map(lsp_message).collect();
+    let mut cmd = clean();
+    cmd.arg("lsp").write_stdin(input);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8_lossy(&output);
+    assert!(output.contains("\"documentFormattingProvider\":true"));
+    assert!(output.contains("textDocument/publishDiagnostics"));
+    assert!(output.contains("\"code\":\"trailing_whitespace\""));
+}
+
+const REGEX_RULES: &str = r#"
+[[rules]]
+id = "no_todo"
+message = "TODO left in code"
+pattern = "TODO"
+files = ["*.rs"]
+
+[[rules]]
+id = "typo_teh"
+message = "Typo of \"the\""
+pattern = '\bteh\b'
+replacement = "the"
+"#;
+
+// Test: regex rules of .clean.toml are reported like built-in ones
+#[test]
+fn test_config_regex_rules() {
+    let temp = tempfile::tempdir().unwrap();
+    fs::write(temp.path().join(".clean.toml"), REGEX_RULES).unwrap();
+    fs::write(temp.path().join("a.rs"), "// TODO: teh end\n").unwrap();
+    fs::write(temp.path().join("b.txt"), "TODO\n").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).args(["--git=false", "--json", "a.rs", "b.txt"]);
+    let assert = cmd.assert().failure();
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    let expected = serde_json::json!([
+        {"type": "no_todo", "line": 1, "file": "a.rs", "severity": "error", "message": "TODO left in code"},
+        {"type": "typo_teh", "line": 1, "file": "a.rs", "severity": "error", "message": "Typo of \"the\""},
+    ]);
+    assert_eq!(json, expected);
+}
+
+// Test: --fix applies the replacement of regex rules
+#[test]
+fn test_config_regex_rule_fix() {
+    let temp = tempfile::tempdir().unwrap();
+    let config = temp.path().join("rules.toml");
+    fs::write(&config, REGEX_RULES).unwrap();
+    let file_path = temp.path().join("a.txt");
+    fs::write(&file_path, "teh cat \nsteh\n").unwrap();
+    let mut cmd = clean();
+    cmd.arg("--config").arg(&config).arg("--fix").arg(&file_path);
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "the cat\nsteh\n");
+}
+
+// Test: an invalid config file is an error naming the file
+#[test]
+fn test_config_invalid() {
+    let temp = tempfile::tempdir().unwrap();
+    let config = temp.path().join("rules.toml");
+    fs::write(temp.path().join("a.txt"), "ok\n").unwrap();
+    for text in [
+        "[[rules]]\nid = \"x\"\nmessage = \"m\"\npattern = \"(\"\n",
+        "[[rules]]\nid = \"trailing_whitespace\"\nmessage = \"m\"\npattern = \"a\"\n",
+        "[[rule]]\n",
+    ] {
+        fs::write(&config, text).unwrap();
+        let mut cmd = clean();
+        cmd.arg("--config").arg(&config).arg(temp.path().join("a.txt"));
+        let assert = cmd.assert().failure();
+        let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+        assert!(stderr.contains("invalid config"), "{}", stderr);
+        assert!(stderr.contains("rules.toml"), "{}", stderr);
+    }
+}
+
+/// Writes a config with a single plugin running `script` with `sh -c`.
+fn plugin_config(dir: &std::path::Path, script: &str, extra: &str) -> std::path::PathBuf {
+    let config = dir.join("plugins.toml");
+    let text = format!(
+        "[[plugins]]\nid = \"my_plugin\"\nmessage = \"Reported by my plugin\"\ncommand = [\"sh\", \"-c\", {}]\n{}",
+        toml_string(script),
+        extra
+    );
+    fs::write(&config, text).unwrap();
+    config
+}
+
+fn toml_string(s: &str) -> String {
+    serde_json::Value::String(s.to_string()).to_string()
+}
+
+// Test: plugins get the path, content and settings, their issues are reported
+#[test]
+fn test_plugin_issues() {
+    let temp = tempfile::tempdir().unwrap();
+    let request = temp.path().join("request.json");
+    let script = format!(
+        "cat > '{}'; echo '{{\"issues\": [{{\"line\": 2, \"message\": \"bad line\"}}, {{\"line\": 1}}]}}'",
+        request.display()
+    );
+    let config = plugin_config(temp.path(), &script, "settings = { level = 3 }\n");
+    let file_path = temp.path().join("a.txt");
+    fs::write(&file_path, "one\ntwo\n").unwrap();
+    let mut cmd = clean();
+    cmd.arg("--config").arg(&config).arg("--json").arg(&file_path);
+    let assert = cmd.assert().failure();
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    let file = file_path.to_string_lossy().to_string();
+    let expected = serde_json::json!([
+        {"type": "my_plugin", "line": 1, "file": file, "severity": "error", "message": "Reported by my plugin"},
+        {"type": "my_plugin", "line": 2, "file": file, "severity": "error", "message": "bad line"},
+    ]);
+    assert_eq!(json, expected);
+    let request: serde_json::Value = serde_json::from_slice(&fs::read(&request).unwrap()).unwrap();
+    let expected = serde_json::json!({
+        "path": file,
+        "content": "one\ntwo\n",
+        "settings": {"level": 3},
+    });
+    assert_eq!(request, expected);
+}
+
+// Test: --fix applies the edits of fixable plugins
+#[test]
+fn test_plugin_fix() {
+    let temp = tempfile::tempdir().unwrap();
+    // Line 2 is only reported until it is fixed.
+    let script = "grep -q TWO && echo '{\"issues\": []}' || echo '{\"issues\": [{\"line\": 2}], \"edits\": [{\"line\": 2, \"text\": \"TWO \"}]}'";
+    let config = plugin_config(temp.path(), script, "fixable = true\n");
+    let file_path = temp.path().join("a.txt");
+    fs::write(&file_path, "one\r\ntwo\r\n").unwrap();
+    let mut cmd = clean();
+    cmd.arg("--config").arg(&config).arg("--fix").arg(&file_path);
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "one\nTWO\n");
+}
+
+// Test: plugins reading files get the path of a file with the content
+#[test]
+fn test_plugin_input_path() {
+    let temp = tempfile::tempdir().unwrap();
+    let script = "grep -q '\"content_path\"' && echo '[{\"line\": 1}]'";
+    let config = plugin_config(temp.path(), script, "input = \"path\"\n");
+    let file_path = temp.path().join("a.txt");
+    fs::write(&file_path, "one\n").unwrap();
+    let mut cmd = clean();
+    cmd.arg("--config").arg(&config).arg("--json").arg(&file_path);
+    let assert = cmd.assert().failure();
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(json[0]["type"], "my_plugin");
+}
+
+// Test: a crashing, hanging or misbehaving plugin is a clear error
+#[test]
+fn test_plugin_failures() {
+    let temp = tempfile::tempdir().unwrap();
+    let file_path = temp.path().join("a.txt");
+    fs::write(&file_path, "one\n").unwrap();
+    for (script, extra, message) in [
+        ("echo boom >&2; exit 3", "", "boom"),
+        ("sleep 10", "timeout = 1\n", "timed out after 1s"),
+        ("cat > /dev/null; echo not json", "", "invalid response"),
+    ] {
+        let config = plugin_config(temp.path(), script, extra);
+        let mut cmd = clean();
+        cmd.arg("--config").arg(&config).arg("--no-cache").arg(&file_path);
+        let assert = cmd.assert().failure();
+        let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+        assert!(stderr.contains("rule 'my_plugin' failed"), "{}", stderr);
+        assert!(stderr.contains(message), "{}", stderr);
+    }
+}
+
+// Test: suppressed issues are neither reported nor fixed, unused
+// suppressions are reported
+#[test]
+fn test_inline_suppressions() {
+    let temp = tempfile::tempdir().unwrap();
+    let file_path = temp.path().join("a.md");
+    let content = "<!-- clean:disable trailing_whitespace -->\nhard  \nbreak  \n<!-- clean:enable -->\n# clean:ignore-next-line\nok\ntrailing \n";
+    fs::write(&file_path, content).unwrap();
+    let mut cmd = clean();
+    cmd.arg("--json").arg(&file_path);
+    let assert = cmd.assert().failure();
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    let file = file_path.to_string_lossy().to_string();
+    let expected = serde_json::json!([
+        {"type": "unused_suppression", "line": 5, "file": file, "severity": "error"},
+        {"type": "trailing_whitespace", "line": 7, "file": file, "severity": "error"},
+    ]);
+    assert_eq!(json, expected);
+
+    // The unused suppression can not be fixed, and is left.
+    let mut cmd = clean();
+    cmd.arg("--fix").arg(&file_path);
+    let assert = cmd.assert().code(1);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("`5` error: Unused suppression"), "{}", stdout);
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        content.replace("trailing \n", "trailing\n")
+    );
+}
+
+const OVERRIDES: &str = r#"
+[[overrides]]
+rules.trailing_whitespace = "warning"
+
+[[overrides]]
+files = ["docs/**"]
+rules.trailing_whitespace = "off"
+rules.missing_newline = "info"
+"#;
+
+// Test: overrides set the severity of rules per path, and --fail-on decides
+// which severities fail
+#[test]
+fn test_config_overrides() {
+    let temp = tempfile::tempdir().unwrap();
+    fs::write(temp.path().join(".clean.toml"), OVERRIDES).unwrap();
+    fs::create_dir(temp.path().join("docs")).unwrap();
+    fs::write(temp.path().join("docs/a.md"), "a \nb").unwrap();
+    fs::write(temp.path().join("b.txt"), "b \n").unwrap();
+
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).args(["--git=false", "--json", "docs/a.md", "b.txt"]);
+    let assert = cmd.assert().failure();
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    let expected = serde_json::json!([
+        {"type": "trailing_whitespace", "line": 1, "file": "b.txt", "severity": "warning"},
+        {"type": "missing_newline", "line": 2, "file": "docs/a.md", "severity": "info"},
+    ]);
+    assert_eq!(json, expected);
+
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).args(["--git=false", "--fail-on", "error", "docs/a.md", "b.txt"]);
+    let assert = cmd.assert().success();
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("- **Line:** `1` warning: Trailing whitespace"), "{}", stdout);
+    assert!(stdout.contains("- **Line:** `2` info: Missing newline at end of file"), "{}", stdout);
+
+    // Info issues never fail, and rules turned off are not fixed.
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).args(["--git=false", "docs/a.md"]);
+    cmd.assert().success();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).args(["--git=false", "--fix", "docs/a.md"]);
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(temp.path().join("docs/a.md")).unwrap(), "a \nb\n");
+
+    fs::write(temp.path().join(".clean.toml"), "[[overrides]]\nrules.no_such_rule = \"off\"\n").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).args(["--git=false", "b.txt"]);
+    let assert = cmd.assert().failure();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("unknown rule 'no_such_rule'"), "{}", stderr);
+}
+
+// Test: the globs of .clean.toml are relative to its directory, which is
+// found from the linted path rather than the current directory
+#[test]
+fn test_config_globs_relative_to_config() {
+    let temp = tempfile::tempdir().unwrap();
+    let repo = temp.path().join("repo");
+    fs::create_dir_all(repo.join("docs")).unwrap();
+    let config = format!("{}\n[[rules]]\nid = \"no_todo\"\nmessage = \"TODO\"\npattern = \"TODO\"\nfiles = [\"docs/*.md\"]\n", OVERRIDES);
+    fs::write(repo.join(".clean.toml"), config).unwrap();
+    fs::write(repo.join("docs/a.md"), "a \nTODO\n").unwrap();
+
+    // From outside of the repository, by absolute path.
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("--git=false").arg("--json").arg(&repo);
+    let assert = cmd.assert().failure();
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 1, "{}", json);
+    assert_eq!(json[0]["type"], "no_todo");
+
+    // From the directory of the file.
+    let mut cmd = clean();
+    cmd.current_dir(repo.join("docs")).args(["--git=false", "--json", "a.md"]);
+    let assert = cmd.assert().failure();
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 1, "{}", json);
+    assert_eq!(json[0]["type"], "no_todo");
+}
+
+// Test: the exit status tells issues from usage, I/O and fix failures
+#[test]
+fn test_exit_codes() {
+    use std::os::unix::fs::PermissionsExt;
+    let temp = tempfile::tempdir().unwrap();
+    let clean_file = temp.path().join("clean.txt");
+    fs::write(&clean_file, "ok\n").unwrap();
+    let dirty = temp.path().join("dirty.txt");
+    fs::write(&dirty, "foo \n").unwrap();
+    let run = |args: &[&str]| {
+        let mut cmd = clean();
+        cmd.current_dir(temp.path()).arg("--git=false").args(args);
+        cmd.assert()
+    };
+
+    run(&["clean.txt"]).code(0);
+    run(&["dirty.txt"]).code(1);
+    run(&["--no-such-option"]).code(2);
+    run(&["missing.txt"]).code(2);
+    let assert = run(&["--ignore", "[", "clean.txt"]).code(2);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("Invalid glob pattern"), "{}", stderr);
+    run(&["--config", "missing.toml", "clean.txt"]).code(2);
+    run(&["--output", ".", "clean.txt"]).code(3);
+    run(&["--staged"]).code(3);
+    run(&["--files-from", "missing.list"]).code(3);
+
+    let mut perms = fs::metadata(&dirty).unwrap().permissions();
+    perms.set_mode(0o444);
+    fs::set_permissions(&dirty, perms).unwrap();
+    // Permissions are not enforced for root.
+    if fs::OpenOptions::new().write(true).open(&dirty).is_err() {
+        let assert = run(&["--fix", "--json", "dirty.txt"]).code(4);
+        let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+        assert!(stderr.contains("failed to fix file"), "{}", stderr);
+        let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+        assert_eq!(json[0]["type"], "trailing_whitespace");
+    }
+}
+
+// Test: `rules` lists built-in and custom rules, `explain` describes one
+#[test]
+fn test_rules_and_explain() {
+    let temp = tempfile::tempdir().unwrap();
+    fs::write(temp.path().join(".clean.toml"), REGEX_RULES).unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("rules");
+    let assert = cmd.assert().success();
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let rows: Vec<Vec<&str>> = stdout
+        .lines()
+        .map(|l| l.split_whitespace().take(3).collect())
+        .collect();
+    assert!(rows.contains(&vec!["trailing_whitespace", "error", "yes"]), "{}", stdout);
+    assert!(rows.contains(&vec!["unused_suppression", "error", "no"]), "{}", stdout);
+    assert!(rows.contains(&vec!["typo_teh", "error", "yes"]), "{}", stdout);
+
+    let mut cmd = clean();
+    cmd.args(["explain", "crlf_line_ending"]);
+    let assert = cmd.assert().success();
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.starts_with("crlf_line_ending: Contains CRLF line endings\n"), "{}", stdout);
+    assert!(stdout.contains("Bad:\n\n    first^M$\n"), "{}", stdout);
+    assert!(stdout.contains("Good:\n\n    first$\n"), "{}", stdout);
+
+    let mut cmd = clean();
+    cmd.args(["explain", "no_such_rule"]);
+    cmd.assert().code(2);
+}
+
+// Test: --select only checks and fixes the given rules, --skip leaves out
+// the given rules
+#[test]
+fn test_select_and_skip() {
+    let temp = tempfile::tempdir().unwrap();
+    let file_path = temp.path().join("a.txt");
+    fs::write(&file_path, "a \r\nb").unwrap();
+    let types = |args: &[&str]| {
+        let mut cmd = clean();
+        cmd.arg("--no-cache").arg("--json").args(args).arg(&file_path);
+        let output = cmd.output().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        json.as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["type"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(types(&["--select", "crlf_line_ending"]), ["crlf_line_ending"]);
+    assert_eq!(
+        types(&["--select", "crlf_line_ending,missing_newline"]),
+        ["crlf_line_ending", "missing_newline"]
+    );
+    assert_eq!(
+        types(&["--skip", "trailing_whitespace", "--skip", "missing_newline"]),
+        ["crlf_line_ending"]
+    );
+
+    let mut cmd = clean();
+    cmd.args(["--fix", "--select", "crlf_line_ending"]).arg(&file_path);
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "a \nb");
+
+    let mut cmd = clean();
+    cmd.args(["--skip", "no_such_rule"]).arg(&file_path);
+    let assert = cmd.assert().code(2);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("unknown rule 'no_such_rule'"), "{}", stderr);
+}
+
+// Test: --fix --diff prints the fixes as a unified diff without writing them,
+// and fails if any file would change
+#[test]
+fn test_fix_diff() {
+    let temp = tempfile::tempdir().unwrap();
+    fs::write(temp.path().join("a.txt"), "a \nb\nc\nd\ne\nf\ng\nh\ni\nj \n").unwrap();
+    fs::write(temp.path().join("b.txt"), "ok").unwrap();
+    fs::write(temp.path().join("c.txt"), "clean\n").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path())
+        .args(["--no-cache", "--git=false", "--fix", "--diff", "."]);
+    let assert = cmd.assert().code(1);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let expected = "\
+diff --git a/a.txt b/a.txt
+--- a/a.txt
++++ b/a.txt
+@@ -1,4 +1,4 @@
+-a\x20
++a
+ b
+ c
+ d
+@@ -7,4 +7,4 @@
+ g
+ h
+ i
+-j\x20
++j
+diff --git a/b.txt b/b.txt
+--- a/b.txt
++++ b/b.txt
+@@ -1 +1 @@
+-ok
+\\ No newline at end of file
++ok
+";
+    assert_eq!(stdout, expected);
+    assert_eq!(
+        fs::read_to_string(temp.path().join("a.txt")).unwrap(),
+        "a \nb\nc\nd\ne\nf\ng\nh\ni\nj \n"
+    );
+    assert_eq!(fs::read_to_string(temp.path().join("b.txt")).unwrap(), "ok");
+
+    // Nothing to fix
+    let mut cmd = clean();
+    cmd.current_dir(temp.path())
+        .args(["--no-cache", "--fix", "--diff", "c.txt"]);
+    let assert = cmd.assert().success();
+    assert!(assert.get_output().stdout.is_empty());
+
+    // --diff needs --fix or --check
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).args(["--diff", "c.txt"]);
+    cmd.assert().code(2);
+}
+
+// Test: a diff saved with --output is a patch that git apply accepts, with
+// the same result as --fix
+#[test]
+fn test_fix_diff_patch() {
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    let content = "x\r\ny \r\n\tz\n\n\n";
+    fs::write(temp.path().join("a.txt"), content).unwrap();
+    SysCommand::new("git").arg("init").current_dir(temp.path()).output().unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path())
+        .args(["--no-cache", "--fix", "--diff", "-o", "fix.patch", "a.txt"]);
+    cmd.assert().code(1);
+    let patch = temp.path().join("fix.patch");
+    let status = SysCommand::new("git")
+        .arg("apply")
+        .arg(&patch)
+        .current_dir(temp.path())
+        .status()
+        .unwrap();
+    fs::remove_file(&patch).unwrap();
+    assert!(status.success());
+    let applied = fs::read_to_string(temp.path().join("a.txt")).unwrap();
+
+    fs::write(temp.path().join("a.txt"), content).unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).args(["--no-cache", "--fix", "a.txt"]);
+    cmd.assert().success();
+    assert_eq!(applied, fs::read_to_string(temp.path().join("a.txt")).unwrap());
+}
+
+// Test: --check reports issues without fixing them, and fails only if
+// fixing would change a file
+#[test]
+fn test_check() {
+    let temp = tempfile::tempdir().unwrap();
+    let file_path = temp.path().join("a.txt");
+    fs::write(&file_path, "a \n").unwrap();
+    let mut cmd = clean();
+    cmd.arg("--no-cache").arg("--check").arg(&file_path);
+    let assert = cmd.assert().code(1);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("Trailing whitespace"), "{}", stdout);
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "a \n");
+
+    // An unused suppression can not be fixed
+    fs::write(&file_path, "# clean:disable-file trailing_whitespace\n").unwrap();
+    let mut cmd = clean();
+    cmd.arg("--no-cache").arg("--check").arg(&file_path);
+    let assert = cmd.assert().success();
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("Unused suppression"), "{}", stdout);
+
+    let mut cmd = clean();
+    cmd.args(["--no-cache", "--check", "--diff", "--stdin"])
+        .write_stdin("a\t\n");
+    let assert = cmd.assert().code(1);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("-a\t\n+a\n"), "{}", stdout);
+}
+
+// Test: --fix replaces files keeping their mode and owner, without leaving
+// temporary files behind
+#[test]
+fn test_fix_keeps_metadata() {
+    use std::os::unix::fs::{chown, MetadataExt, PermissionsExt};
+    let temp = tempfile::tempdir().unwrap();
+    let file_path = temp.path().join("run.sh");
+    fs::write(&file_path, "echo hi \n").unwrap();
+    fs::set_permissions(&file_path, fs::Permissions::from_mode(0o751)).unwrap();
+    // Only root can give files away.
+    let root = fs::metadata(&file_path).unwrap().uid() == 0;
+    if root {
+        chown(&file_path, Some(65534), Some(65534)).unwrap();
+    }
+    let mut cmd = clean();
+    cmd.arg("--no-cache").arg("--fix").arg(&file_path);
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "echo hi\n");
+    let md = fs::metadata(&file_path).unwrap();
+    assert_eq!(md.permissions().mode() & 0o7777, 0o751);
+    if root {
+        assert_eq!((md.uid(), md.gid()), (65534, 65534));
+    }
+    let names: Vec<_> = fs::read_dir(temp.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(names, ["run.sh"]);
+}
+
+// Test: --fix skips symbolic links by default, and with --symlinks follow
+// only fixes targets inside the linted directories
+#[test]
+fn test_fix_symlinks() {
+    use std::os::unix::fs::symlink;
+    let outside = tempfile::tempdir().unwrap();
+    let temp = tempfile::tempdir().unwrap();
+    fs::write(temp.path().join("a.txt"), "a \n").unwrap();
+    fs::write(outside.path().join("b.txt"), "b \n").unwrap();
+    symlink("a.txt", temp.path().join("link.txt")).unwrap();
+    symlink(outside.path().join("b.txt"), temp.path().join("out.txt")).unwrap();
+    let fix = |args: &[&str]| {
+        let mut cmd = clean();
+        cmd.current_dir(temp.path())
+            .args(["--no-cache", "--fix", "link.txt", "out.txt"])
+            .args(args);
+        cmd.assert().code(1)
+    };
+    let assert = fix(&[]);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("## link.txt"), "{}", stdout);
+    assert_eq!(fs::read_to_string(temp.path().join("a.txt")).unwrap(), "a \n");
+
+    let assert = fix(&["--symlinks", "follow"]);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(!stdout.contains("## link.txt"), "{}", stdout);
+    assert!(stdout.contains("## out.txt"), "{}", stdout);
+    assert_eq!(fs::read_to_string(temp.path().join("a.txt")).unwrap(), "a\n");
+    assert!(fs::symlink_metadata(temp.path().join("link.txt"))
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(fs::read_to_string(outside.path().join("b.txt")).unwrap(), "b \n");
+}
+
+// Test: --fix skips files with several hard links by default, --hard-links
+// break fixes only the given name and in-place fixes all of them
+#[test]
+fn test_fix_hard_links() {
+    let temp = tempfile::tempdir().unwrap();
+    let a = temp.path().join("a.txt");
+    let b = temp.path().join("b.txt");
+    fs::write(&a, "a \n").unwrap();
+    fs::hard_link(&a, &b).unwrap();
+    let fix = |policy: Option<&str>| {
+        let mut cmd = clean();
+        cmd.arg("--no-cache").arg("--fix").arg(&a);
+        if let Some(policy) = policy {
+            cmd.args(["--hard-links", policy]);
+        }
+        cmd.output().unwrap().status.code()
+    };
+    assert_eq!(fix(None), Some(1));
+    assert_eq!(fs::read_to_string(&a).unwrap(), "a \n");
+
+    assert_eq!(fix(Some("in-place")), Some(0));
+    assert_eq!(fs::read_to_string(&a).unwrap(), "a\n");
+    assert_eq!(fs::read_to_string(&b).unwrap(), "a\n");
+
+    fs::write(&a, "a \n").unwrap();
+    assert_eq!(fix(Some("break")), Some(0));
+    assert_eq!(fs::read_to_string(&a).unwrap(), "a\n");
+    assert_eq!(fs::read_to_string(&b).unwrap(), "a \n");
+}
+
+// Test: --backup keeps a copy of each fixed file
+#[test]
+fn test_fix_backup() {
+    let temp = tempfile::tempdir().unwrap();
+    let file_path = temp.path().join("a.txt");
+    fs::write(&file_path, "a \n").unwrap();
+    let mut cmd = clean();
+    cmd.args(["--no-cache", "--fix", "--backup"])
+        .arg(&file_path);
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "a\n");
+    assert_eq!(fs::read_to_string(temp.path().join("a.txt~")).unwrap(), "a \n");
+
+    fs::write(&file_path, "b \n").unwrap();
+    let mut cmd = clean();
+    cmd.args(["--no-cache", "--fix", "--backup=.orig"])
+        .arg(&file_path);
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(temp.path().join("a.txt.orig")).unwrap(), "b \n");
+
+    // --backup needs --fix
+    let mut cmd = clean();
+    cmd.args(["--no-cache", "--backup"]).arg(&file_path);
+    cmd.assert().code(2);
+}
+
+// Test: undo restores the files of the last --fix run, or of a given run,
+// but not files changed since unless --force is given
+#[test]
+fn test_undo() {
+    let temp = tempfile::tempdir().unwrap();
+    let a = temp.path().join("a.txt");
+    let b = temp.path().join("b.txt");
+    let run = |args: &[&str]| {
+        let mut cmd = clean();
+        cmd.current_dir(temp.path()).args(args);
+        cmd.output().unwrap()
+    };
+    fs::write(&a, "a \n").unwrap();
+    fs::write(&b, "b\r\n").unwrap();
+    assert!(run(&["--no-cache", "--fix", "a.txt", "b.txt"]).status.success());
+    fs::write(&a, "a\t\n").unwrap();
+    assert!(run(&["--no-cache", "--fix", "a.txt"]).status.success());
+    assert_eq!(fs::read_to_string(&a).unwrap(), "a\n");
+
+    let output = run(&["undo", "--list"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let runs: Vec<&str> = stdout.lines().collect();
+    assert_eq!(runs.len(), 2, "{}", stdout);
+    assert!(runs[0].ends_with("  1 file"), "{}", stdout);
+    assert!(runs[1].ends_with("  2 files"), "{}", stdout);
+    let first = runs[1].split_whitespace().next().unwrap();
+
+    // The last run
+    assert!(run(&["undo"]).status.success());
+    assert_eq!(fs::read_to_string(&a).unwrap(), "a\t\n");
+
+    // b.txt changed since the first run
+    fs::write(&b, "changed\n").unwrap();
+    fs::write(&a, "a\n").unwrap();
+    let output = run(&["undo", first]);
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("use --force"), "{}", stderr);
+    assert_eq!(fs::read_to_string(&a).unwrap(), "a \n");
+    assert_eq!(fs::read_to_string(&b).unwrap(), "changed\n");
+
+    assert!(run(&["undo", "--force", first]).status.success());
+    assert_eq!(fs::read_to_string(&b).unwrap(), "b\r\n");
+    assert_eq!(run(&["undo"]).status.code(), Some(2));
+    assert_eq!(run(&["undo", first]).status.code(), Some(2));
+}
+
+// Test: --fix reports the fixed files with the rules applied, and the issues
+// left, which fail
+#[test]
+fn test_fix_summary() {
+    let temp = tempfile::tempdir().unwrap();
+    fs::write(temp.path().join("a.txt"), "a \nb \nc").unwrap();
+    fs::write(temp.path().join("b.txt"), "b\r\n").unwrap();
+    let unused = "# clean:ignore-next-line crlf_line_ending\nx \n";
+    fs::write(temp.path().join("c.txt"), unused).unwrap();
+    fs::write(temp.path().join("d.txt"), "clean\n").unwrap();
+    let setup = || {
+        let mut cmd = clean();
+        cmd.current_dir(temp.path())
+            .args(["--no-cache", "--fix", "a.txt", "b.txt", "c.txt", "d.txt"]);
+        cmd
+    };
+    let assert = setup().assert().code(1);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let expected = "\
+# Clean report
+
+## Fixed
+
+- `a.txt`: missing_newline (1), trailing_whitespace (2)
+- `b.txt`: crlf_line_ending (1), trailing_whitespace (1)
+- `c.txt`: trailing_whitespace (1)
+
+Fixed 6 issues in 3 files: crlf_line_ending (1), missing_newline (1), trailing_whitespace (4).
+
+## c.txt
+
+- **Line:** `1` error: Unused suppression of crlf_line_ending
+
+1 issue could not be fixed.
+
+";
+    assert_eq!(stdout, expected);
+
+    fs::write(temp.path().join("a.txt"), " a\t\n").unwrap();
+    let assert = setup().arg("--json").assert().code(1);
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    let expected = serde_json::json!({
+        "fixed": [{"file": "a.txt", "rules": {"trailing_whitespace": 1}}],
+        "rules": {"trailing_whitespace": 1},
+        "issues": [
+            {
+                "type": "unused_suppression",
+                "line": 1,
+                "file": "c.txt",
+                "severity": "error",
+                "message": "Unused suppression of crlf_line_ending",
+            },
+        ],
+    });
+    assert_eq!(json, expected);
+
+    fs::write(temp.path().join("c.txt"), "ok\n").unwrap();
+    let assert = setup().assert().success();
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert_eq!(stdout, "# Clean report\n\n\nNo lint issues found.\n\n");
+}
+
+// Test: undo leaves files alone that a run recorded but never replaced, as
+// when it was interrupted
+#[test]
+fn test_undo_unchanged_file() {
+    let temp = tempfile::tempdir().unwrap();
+    let a = temp.path().join("a.txt");
+    fs::write(&a, "a \n").unwrap();
+    let mut cmd = clean();
+    cmd.args(["--no-cache", "--fix"]).arg(&a);
+    cmd.assert().success();
+    // As if the run ended between recording and replacing the file
+    fs::write(&a, "a \n").unwrap();
+    clean().arg("undo").assert().success();
+    assert_eq!(fs::read_to_string(&a).unwrap(), "a \n");
+    let assert = clean().args(["undo", "--list"]).assert().success();
+    assert!(assert.get_output().stdout.is_empty());
+}
+
+// Test: --fix fixes files without a state directory to save them in, and
+// warns once
+#[test]
+fn test_fix_without_journal() {
+    let temp = tempfile::tempdir().unwrap();
+    fs::write(temp.path().join("a.txt"), "a \n").unwrap();
+    fs::write(temp.path().join("b.txt"), "b \n").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).args(["--no-cache", "--fix", "a.txt", "b.txt"]);
+    cmd.env_remove("HOME").env_remove("XDG_STATE_HOME");
+    let output = cmd.assert().success().get_output().stderr.clone();
+    let stderr = String::from_utf8_lossy(&output);
+    assert_eq!(stderr.matches("failed to save fixed files").count(), 1, "{}", stderr);
+    assert_eq!(fs::read_to_string(temp.path().join("a.txt")).unwrap(), "a\n");
+    assert_eq!(fs::read_to_string(temp.path().join("b.txt")).unwrap(), "b\n");
+}
+
+// Test: --no-journal fixes files without saving them for undo
+#[test]
+fn test_fix_no_journal() {
+    let temp = tempfile::tempdir().unwrap();
+    let a = temp.path().join("a.txt");
+    fs::write(&a, "a \n").unwrap();
+    let mut cmd = clean();
+    cmd.args(["--no-cache", "--fix", "--no-journal"]).arg(&a);
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&a).unwrap(), "a\n");
+    assert!(!xdg_env()[0].1.join("clean").exists());
+    clean().arg("--no-journal").arg(&a).assert().code(2);
+}
+
+// Test: a chained pre-push hook still reads the pushed refs after clean
+#[test]
+fn test_install_pre_push_hook_keeps_stdin() {
+    use std::io::Write;
+    use std::process::{Command as SysCommand, Stdio};
+    let temp = tempfile::tempdir().unwrap();
+    SysCommand::new("git").arg("init").current_dir(temp.path()).output().unwrap();
+    let hook = temp.path().join(".git").join("hooks").join("pre-push");
+    fs::create_dir_all(hook.parent().unwrap()).unwrap();
+    fs::write(&hook, "#!/bin/sh\ncat > seen.txt\n").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("install-hook").arg("pre-push");
+    cmd.assert().success();
+
+    // Deleting a remote branch pushes nothing to lint.
+    let zero = "0".repeat(40);
+    let refs = format!("(delete) {} refs/heads/gone {}\n", zero, "1".repeat(40));
+    let mut child = SysCommand::new("sh")
+        .envs(xdg_env())
+        .arg(&hook)
+        .args(["origin", "url"])
+        .current_dir(temp.path())
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(refs.as_bytes()).unwrap();
+    assert!(child.wait().unwrap().success());
+    assert_eq!(fs::read_to_string(temp.path().join("seen.txt")).unwrap(), refs);
+}
+
+// Test: install-hook moves a hook in another language aside and runs it
+// from a shell script, uninstalling moves it back
+#[test]
+fn test_install_hook_non_shell() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    SysCommand::new("git").arg("init").current_dir(temp.path()).output().unwrap();
+    let hook = temp.path().join(".git").join("hooks").join("pre-commit");
+    let local = hook.with_file_name("pre-commit.local");
+    fs::create_dir_all(hook.parent().unwrap()).unwrap();
+    let original = "#!/usr/bin/env perl\nopen(my $f, '>', 'ran.txt');\nprint $f \"@ARGV\";\n";
+    fs::write(&hook, original).unwrap();
+    fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("install-hook").arg("pre-commit");
+    cmd.assert().success();
+    let installed = fs::read_to_string(&hook).unwrap();
+    assert!(installed.starts_with("#!/bin/sh\n# >>> clean >>>\n"));
+    assert_eq!(fs::read_to_string(&local).unwrap(), original);
+    // Installing twice keeps the moved hook
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("install-hook");
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&hook).unwrap(), installed);
+    assert_eq!(fs::read_to_string(&local).unwrap(), original);
+
+    let status = SysCommand::new(&hook)
+        .envs(xdg_env())
+        .args(["a", "b"])
+        .current_dir(temp.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(fs::read_to_string(temp.path().join("ran.txt")).unwrap(), "a b");
+
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("install-hook").arg("--uninstall");
+    cmd.assert().success();
+    assert_eq!(fs::read_to_string(&hook).unwrap(), original);
+    assert!(!local.exists());
+}
+
+// Test: a hook whose clean binary was moved runs clean from PATH
+#[test]
+fn test_install_hook_moved_binary() {
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    SysCommand::new("git").arg("init").current_dir(temp.path()).output().unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path()).arg("install-hook").arg("pre-commit");
+    cmd.assert().success();
+    let hook = temp.path().join(".git").join("hooks").join("pre-commit");
+    let exe = assert_cmd::cargo::cargo_bin("clean");
+    let installed = fs::read_to_string(&hook).unwrap();
+    let exe_line = format!("clean_exe='{}'\n", exe.display());
+    assert!(installed.contains(&exe_line));
+    fs::write(&hook, installed.replace(&exe_line, "clean_exe='/nonexistent/clean'\n")).unwrap();
+    let run = |path: &std::ffi::OsStr| {
+        SysCommand::new("/bin/sh")
+            .envs(xdg_env())
+            .env("PATH", path)
+            .arg(&hook)
+            .current_dir(temp.path())
+            .status()
+            .unwrap()
+    };
+    let path = std::env::join_paths(
+        [exe.parent().unwrap().to_path_buf()]
+            .into_iter()
+            .chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    assert!(run(&path).success());
+    assert_eq!(run(std::ffi::OsStr::new("/nonexistent")).code(), Some(127));
+}
+
+// Test: listed files get the attributes of their own work tree, looked up in
+// one batch per work tree
+#[test]
+fn test_files_from_attributes_per_work_tree() {
+    use std::process::Command as SysCommand;
+    let temp = tempfile::tempdir().unwrap();
+    let mut list = String::new();
+    for repo in ["a", "b"] {
+        let dir = temp.path().join(repo);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        SysCommand::new("git").arg("init").current_dir(&dir).output().unwrap();
+        fs::write(dir.join("sub/doc.md"), "doc \n").unwrap();
+        list.push_str(&format!("{}/sub/doc.md\n", dir.display()));
+        // Enough paths that git blocks on its output while they are written.
+        for i in 0..2000 {
+            let file = dir.join("sub").join(format!("{}.txt", i));
+            fs::write(&file, "ok\n").unwrap();
+            list.push_str(&format!("{}\n", file.display()));
+        }
+    }
+    fs::write(temp.path().join("a/.gitattributes"), "*.md -text\n").unwrap();
+    let mut cmd = clean();
+    cmd.current_dir(temp.path())
+        .args(["--json", "--no-cache", "--files-from", "-"])
+        .write_stdin(list);
+    let output = cmd.assert().code(1).get_output().stdout.clone();
+    let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    let files: Vec<&str> = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["file"].as_str().unwrap())
+        .collect();
+    assert_eq!(files, [format!("{}/b/sub/doc.md", temp.path().display())]);
+}
@@ -0,0 +1,411 @@
+// SPDX-FileCopyrightText: Copyright (C) 2025 Chen Linxuan <me@black-desk.cn>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use clean::rule::{FixedLine, Line};
+use clean::{
+    Error, Issue, IssueType, Linter, Override, RegexRule, Registry, Rule, Severity, WsRule,
+};
+use std::fs;
+use std::sync::Arc;
+
+// Test: lint_str reports issues of a string and fix_str fixes them
+#[test]
+fn test_lint_and_fix_str() {
+    let linter = Linter::builder().build().unwrap();
+    let issues = linter.lint_str("a.txt", "hello \r\n\n\n").unwrap();
+    let types: Vec<_> = issues.iter().map(|i| i.issue_type.clone()).collect();
+    assert_eq!(
+        types,
+        [
+            IssueType::TrailingWhitespace,
+            IssueType::CrlfLineEnding,
+            IssueType::MultipleBlankLinesEof
+        ]
+    );
+    assert!(issues.iter().all(|i| i.file == "a.txt"));
+    assert_eq!(linter.fix_str("a.txt", "hello \r\n\n\n"), "hello\n");
+    assert!(linter.lint_str("a.txt", "hello\n").unwrap().is_empty());
+    // An empty file is left empty, so it does not lack a newline either.
+    assert!(linter.lint_str("a.txt", "").unwrap().is_empty());
+    assert_eq!(linter.fix_str("a.txt", ""), "");
+}
+
+// Test: the rule set on the builder decides which issues are reported
+#[test]
+fn test_linter_rule() {
+    let linter = Linter::builder()
+        .rule(WsRule::parse("tab-in-indent,-blank-at-eol").unwrap())
+        .build()
+        .unwrap();
+    let issues = linter.lint_str("a.txt", "\thello \n").unwrap();
+    assert_eq!(issues, [Issue::new("a.txt", IssueType::TabInIndent, 1)]);
+    assert_eq!(linter.fix_str("a.txt", "\thello \n"), "        hello \n");
+}
+
+// Test: ignored paths are not linted, and invalid patterns are rejected
+#[test]
+fn test_linter_ignore() {
+    let linter = Linter::builder().ignore("*.md").build().unwrap();
+    assert!(linter.lint_str("docs/README.md", "hello ").unwrap().is_empty());
+    assert_eq!(linter.lint_str("README.txt", "hello ").unwrap().len(), 2);
+    let err = Linter::builder().ignore("[").build().err().unwrap();
+    assert!(matches!(err, Error::Pattern(_)));
+}
+
+// Test: lint_path reads files and reports typed errors
+#[test]
+fn test_lint_path() {
+    let temp = tempfile::tempdir().unwrap();
+    let text = temp.path().join("a.txt");
+    fs::write(&text, "hello\n\n\n").unwrap();
+    let binary = temp.path().join("b.bin");
+    fs::write(&binary, b"\xff \n").unwrap();
+
+    let linter = Linter::builder().build().unwrap();
+    let issues = linter.lint_path(&text).unwrap();
+    assert_eq!(
+        issues,
+        [Issue::new(
+            &text.to_string_lossy(),
+            IssueType::MultipleBlankLinesEof,
+            4
+        )]
+    );
+    match linter.lint_path(&binary) {
+        Err(Error::NotUtf8(path)) => assert_eq!(path, binary),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let missing = linter.lint_path(temp.path().join("missing"));
+    assert!(matches!(missing, Err(Error::Io(_))));
+}
+
+// Test: issues round-trip through the JSON report format
+#[test]
+fn test_issue_deserialize() {
+    let json = r#"[{"type": "trailing_whitespace", "line": 1, "file": "a.txt"}]"#;
+    let issues: Vec<Issue> = serde_json::from_str(json).unwrap();
+    assert_eq!(issues[0].issue_type, IssueType::TrailingWhitespace);
+    assert_eq!(issues[0].line, Some(1));
+    assert_eq!(issues[0].message, None);
+}
+
+/// A rule that reports and removes TODO markers.
+struct NoTodo;
+
+impl Rule for NoTodo {
+    fn id(&self) -> &str {
+        "no_todo"
+    }
+
+    fn description(&self) -> &str {
+        "TODO marker"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check_line(&self, line: &Line<'_>, _ws: &WsRule) -> bool {
+        line.text.starts_with(b"TODO")
+    }
+
+    fn fixable(&self) -> bool {
+        true
+    }
+
+    fn fix_line(&self, line: &mut FixedLine, _ws: &WsRule) {
+        if let Some(rest) = line.text.strip_prefix("TODO") {
+            line.text = rest.trim_start().to_string();
+        }
+    }
+}
+
+// Test: custom rules run next to the built-in ones, and replace rules with
+// the same id
+#[test]
+fn test_custom_rule() {
+    let mut registry = Registry::default();
+    registry.register(Arc::new(NoTodo));
+    assert_eq!(
+        registry.get("no_todo").unwrap().default_severity(),
+        Severity::Warning
+    );
+    let linter = Linter::builder()
+        .registry(registry.clone())
+        .build()
+        .unwrap();
+    let issues = linter.lint_str("a.txt", "TODO fix \r\n").unwrap();
+    let types: Vec<_> = issues.iter().map(|i| i.issue_type.id()).collect();
+    assert_eq!(
+        types,
+        ["trailing_whitespace", "crlf_line_ending", "no_todo"]
+    );
+    assert_eq!(issues[2].issue_type, IssueType::Custom("no_todo".into()));
+    assert_eq!(issues[2].message.as_deref(), Some("TODO marker"));
+    assert_eq!(linter.fix_str("a.txt", "TODO fix \r\n"), "fix\n");
+
+    let mut registry = Registry::new();
+    registry.register(Arc::new(NoTodo));
+    let linter = Linter::builder().registry(registry).build().unwrap();
+    assert_eq!(linter.lint_str("a.txt", "TODO fix \r\n").unwrap().len(), 1);
+    assert_eq!(linter.fix_str("a.txt", "TODO fix \r\n"), "fix \r\n");
+}
+
+#[test]
+fn test_regex_rule() {
+    let rule = RegexRule::new("no_tabs", "Tab character", "\t")
+        .unwrap()
+        .severity(Severity::Warning)
+        .files(vec!["*.py".into()])
+        .unwrap()
+        .replacement(Some("    ".into()));
+    let mut registry = Registry::default();
+    registry.register(Arc::new(rule));
+    let issues = registry
+        .lint("a.py", b"x = 1\t# y\n", &WsRule::default())
+        .unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].issue_type, IssueType::Custom("no_tabs".into()));
+    assert_eq!(issues[0].message.as_deref(), Some("Tab character"));
+    assert!(registry
+        .lint("a.txt", b"x\ty\n", &WsRule::default())
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        registry.fix("a.py", "x = 1\t# y\n", &WsRule::default()),
+        "x = 1    # y\n"
+    );
+    assert_eq!(
+        registry.get("no_tabs").unwrap().default_severity(),
+        Severity::Warning
+    );
+
+    assert!(matches!(
+        RegexRule::new("bad id", "m", "x"),
+        Err(Error::InvalidRule { .. })
+    ));
+    assert!(matches!(
+        RegexRule::new("x", "m", "("),
+        Err(Error::InvalidRule { .. })
+    ));
+}
+
+// Test: suppression directives hide issues, and are reported when unused
+#[test]
+fn test_suppressions() {
+    let ws = WsRule::default();
+    let content = [
+        "a ",
+        "# clean:ignore-next-line trailing_whitespace",
+        "b ",
+        "<!-- clean:disable -->",
+        "c \r",
+        "<!-- clean:enable -->",
+        "d",
+        "# clean:ignore-next-line crlf_line_ending, trailing_whitespace",
+        "e ",
+        "",
+    ]
+    .join("\n");
+    let content = content.as_str();
+    let issues = clean::lint_file("a.md", content.as_bytes(), &ws);
+    let found: Vec<_> = issues
+        .iter()
+        .map(|i| (i.issue_type.id(), i.line.unwrap(), i.message.clone().unwrap()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("trailing_whitespace", 1, "Trailing whitespace".to_string()),
+            (
+                "unused_suppression",
+                8,
+                "Unused suppression of crlf_line_ending".to_string()
+            ),
+        ]
+    );
+    assert_eq!(
+        clean::fix_file(content, &ws),
+        content.replacen("a \n", "a\n", 1)
+    );
+
+    let content = "x \n\n\n# clean:disable-file multiple_blank_lines_eof\n\n\n";
+    let issues = clean::lint_file("a.txt", content.as_bytes(), &ws);
+    assert_eq!(issues, [Issue::new("a.txt", IssueType::TrailingWhitespace, 1)]);
+    assert_eq!(
+        clean::fix_file(content, &ws),
+        "x\n\n\n# clean:disable-file multiple_blank_lines_eof\n\n\n"
+    );
+
+    // Directives outside of comments, such as quoted in prose or in
+    // strings, are not directives.
+    for content in [
+        "Use `clean:disable-file` to skip a file.\nx \n",
+        "let s = \"# clean:disable-file\";\nx \n",
+        "myclean:disable-file\nx \n",
+    ] {
+        let issues = clean::lint_file("a.md", content.as_bytes(), &ws);
+        assert_eq!(issues, [Issue::new("a.md", IssueType::TrailingWhitespace, 2)]);
+    }
+}
+
+// Test: overrides change the severity of rules in matching files, or turn
+// them off
+#[test]
+fn test_overrides() {
+    let mut registry = Registry::default();
+    registry.add_override(
+        Override::new(vec!["*.md".into()])
+            .unwrap()
+            .rule("trailing_whitespace", None)
+            .rule("missing_newline", Some(Severity::Warning)),
+    );
+    assert_eq!(registry.severity("trailing_whitespace", "a.md"), None);
+    assert_eq!(
+        registry.severity("trailing_whitespace", "a.txt"),
+        Some(Severity::Error)
+    );
+    let issues = registry
+        .lint("a.md", b"a \nb", &WsRule::default())
+        .unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].issue_type, IssueType::MissingNewline);
+    assert_eq!(issues[0].severity, Severity::Warning);
+    assert_eq!(registry.fix("a.md", "a \nb", &WsRule::default()), "a \nb\n");
+    assert!(matches!(
+        Override::new(vec!["[".into()]),
+        Err(Error::Pattern(_))
+    ));
+}
+
+// Test: the examples of all built-in rules show what they report and fix,
+// so that `clean explain` cannot drift from the rules
+#[test]
+fn test_rule_examples() {
+    let registry = Registry::default();
+    for rule in registry.rules() {
+        let example = rule
+            .example()
+            .unwrap_or_else(|| panic!("{} has no example", rule.id()));
+        assert!(!rule.explanation().is_empty(), "{}", rule.id());
+        let ws = WsRule::parse(example.whitespace).unwrap();
+        let issues = registry.lint("a.txt", example.bad.as_bytes(), &ws).unwrap();
+        assert!(
+            issues.iter().any(|i| i.issue_type.id() == rule.id()),
+            "{}: {:?}",
+            rule.id(),
+            issues
+        );
+        let issues = registry.lint("a.txt", example.good.as_bytes(), &ws).unwrap();
+        assert_eq!(issues, [], "{}", rule.id());
+        if rule.fixable() {
+            assert_eq!(
+                registry.fix("a.txt", example.bad, &ws),
+                example.good,
+                "{}",
+                rule.id()
+            );
+        }
+    }
+}
+
+/// A reader returning at most a few bytes at a time, splitting characters.
+struct Trickle<'a>(&'a [u8]);
+
+impl std::io::Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(self.0.len()).min(3);
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+// Test: streaming finds the same issues as linting the whole content, also
+// at the end of lines longer than it keeps in memory
+#[test]
+fn test_lint_reader() {
+    use clean::stream::{lint_reader, MAX_LINE};
+    let registry = Registry::default();
+    let ws = WsRule::default();
+    let long = format!("{}\u{3000}\r\n", "é".repeat(MAX_LINE));
+    for content in [
+        "héllo \r\nwörld\t\n\n\n",
+        "a\rb\r\nc\r",
+        "\t  x\u{3000}\nno newline",
+        &long,
+    ] {
+        let streamed = lint_reader(&registry, "a.txt", Trickle(content.as_bytes()), &ws).unwrap();
+        let whole = registry.lint("a.txt", content.as_bytes(), &ws).unwrap();
+        assert_eq!(streamed, whole, "{:?}", content);
+    }
+    let streamed = lint_reader(&registry, "a.txt", Trickle(long.as_bytes()), &ws).unwrap();
+    let types: Vec<_> = streamed.iter().map(|i| i.issue_type.id()).collect();
+    assert_eq!(types, ["trailing_whitespace", "crlf_line_ending"]);
+
+    for invalid in [&b"a\xff\n"[..], b"\xc3\n", b"a\xe2\x82"] {
+        assert!(matches!(
+            lint_reader(&registry, "a.txt", Trickle(invalid), &ws),
+            Err(Error::NotUtf8(_))
+        ));
+    }
+}
+
+// Test: rules that look at whole lines get all of a long streamed line
+#[test]
+fn test_lint_reader_whole_lines() {
+    use clean::stream::{lint_reader, MAX_LINE};
+    let rule = RegexRule::new("no_todo", "TODO", "TODO").unwrap();
+    let mut registry = Registry::default();
+    registry.register(Arc::new(rule));
+    let ws = WsRule::default();
+    let half = "x".repeat(MAX_LINE);
+    let content = format!("{}TODO{} \n", half, half);
+    let streamed = lint_reader(&registry, "a.txt", Trickle(content.as_bytes()), &ws).unwrap();
+    let whole = registry.lint("a.txt", content.as_bytes(), &ws).unwrap();
+    assert_eq!(streamed, whole);
+    let types: Vec<_> = streamed.iter().map(|i| i.issue_type.id()).collect();
+    assert_eq!(types, ["trailing_whitespace", "no_todo"]);
+}
+
+// Test: streamed fixes tell whether they change the content
+#[test]
+fn test_fix_reader_changes() {
+    use clean::stream::{fix_changes, fix_reader};
+    let registry = Registry::default();
+    let ws = WsRule::default();
+    for (content, changed) in [
+        ("clean\n", false),
+        ("", false),
+        ("a\n\n\nb\n", false),
+        ("# clean:ignore-next-line trailing_whitespace\nclean\n", false),
+        ("trailing \n", true),
+        ("no newline", true),
+        ("blank at the end\n\n", true),
+        ("crlf\r\n", true),
+    ] {
+        let mut fixed = Vec::new();
+        let result = fix_reader(&registry, "a.txt", content.as_bytes(), &mut fixed, &ws).unwrap();
+        assert_eq!(result, changed, "{:?}", content);
+        assert_eq!(fixed != content.as_bytes(), changed, "{:?}", content);
+        let result = fix_changes(&registry, "a.txt", content.as_bytes(), &ws).unwrap();
+        assert_eq!(result, changed, "{:?}", content);
+    }
+}
+
+// Test: once a file has a CRLF line ending, every line with a CR is reported
+#[test]
+fn test_crlf_lines() {
+    let linter = Linter::builder().build().unwrap();
+    let lines = |content| -> Vec<Option<usize>> {
+        let issues = linter.lint_str("a.txt", content).unwrap();
+        issues
+            .iter()
+            .filter(|i| i.issue_type == IssueType::CrlfLineEnding)
+            .map(|i| i.line)
+            .collect()
+    };
+    assert_eq!(lines("a\rb\nc\r\nd\r"), [Some(1), Some(2), Some(3)]);
+    assert!(lines("a\rb\nc\r").is_empty());
+}